@@ -0,0 +1,84 @@
+// build.rs - turns the NES 2.0 xml database into the romdb table
+// drop nes20db.xml into data/ to get it compiled in, without it the table is just left empty
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const DB_PATH: &str = "data/nes20db.xml";
+
+// pulls name="value" out of a single tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {}=\"", name);
+    let start = tag.find(&key)? + key.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+fn element<'a>(game: &'a str, name: &str) -> Option<&'a str> {
+    let start = game.find(&format!("<{} ", name))?;
+    let end = game[start..].find('>')?;
+    Some(&game[start..start + end])
+}
+
+// the comment ahead of each entry is the path of the dump it came from
+fn title(game: &str) -> String {
+    let comment = match (game.find("<!--"), game.find("-->")) {
+        (Some(start), Some(end)) if start < end => game[start + 4..end].trim(),
+        _ => "",
+    };
+    let name = comment.rsplit('\\').next().unwrap_or(comment);
+    let name = name.strip_suffix(".nes").unwrap_or(name);
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn entry(game: &str) -> Option<String> {
+    // the whole rom line covers prg + chr, which is what romdb checksums
+    let rom = element(game, "rom")?;
+    let pcb = element(game, "pcb")?;
+    let crc32 = u32::from_str_radix(attribute(rom, "crc32")?, 16).ok()?;
+    let sha1 = attribute(rom, "sha1")?.to_ascii_lowercase();
+    // NES 2.0 mappers past 255 don't fit the header we keep
+    let mapper: u8 = attribute(pcb, "mapper")?.parse().ok()?;
+
+    let mirroring = match attribute(pcb, "mirroring") {
+        Some("H") => "Some(Mirroring::Horizontal)",
+        Some("V") => "Some(Mirroring::Vertical)",
+        Some("4") => "Some(Mirroring::FourScreen)",
+        _ => "None",
+    };
+    let has_battery = attribute(pcb, "battery") == Some("1");
+    let region = match element(game, "console").and_then(|console| attribute(console, "region")) {
+        Some("1") => "Region::PAL",
+        Some("3") => "Region::Dendy",
+        _ => "Region::NTSC",
+    };
+
+    Some(format!(
+        "    GameEntry {{ crc32: 0x{:08X}, sha1: \"{}\", title: \"{}\", region: {}, mapper: {}, mirroring: {}, has_battery: {} }},\n",
+        crc32, sha1, title(game), region, mapper, mirroring, has_battery
+    ))
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // cargo always rebuilds when a watched file is missing, so watch the directory until it's there
+    if Path::new(DB_PATH).exists() {
+        println!("cargo:rerun-if-changed={}", DB_PATH);
+    } else {
+        println!("cargo:rerun-if-changed=data");
+    }
+
+    let mut table = String::from("const DB_GAMES: &[GameEntry] = &[\n");
+    if let Ok(xml) = fs::read_to_string(DB_PATH) {
+        for game in xml.split("<game>").skip(1) {
+            let game = game.split("</game>").next().unwrap_or(game);
+            if let Some(line) = entry(game) {
+                table.push_str(&line);
+            }
+        }
+    }
+    table.push_str("];\n");
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("romdb_games.rs");
+    fs::write(out, table).unwrap();
+}
@@ -0,0 +1,326 @@
+// apu.rs - the 2A03's audio half: five channels, the frame counter and the mixer
+use super::dmc::DMC;
+use super::noise::Noise;
+use super::pulse::Pulse;
+use super::triangle::Triangle;
+use crate::cartridge::Region;
+
+// NTSC, other regions get theirs from Region::cpu_clock
+pub const CPU_CLOCK: u32 = 1_789_773;
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// frame counter steps in cpu cycles, the last only in five step mode
+const FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+// roughly the 90Hz high pass every NES has on its output
+const HIGH_PASS_ALPHA: f32 = 0.987;
+
+pub struct APU {
+    pulse_one: Pulse,
+    pulse_two: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: DMC,
+
+    cpu_clock: u32,
+    frame_steps: [u32; 5],
+    five_step_mode: bool,
+    frame_irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    odd_cycle: bool,
+
+    // box filter down to the output rate
+    sample_clock: u32,
+    sample_sum: f32,
+    sample_count: u32,
+    last_input: f32,
+    last_output: f32,
+    samples: Vec<f32>,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU {
+            pulse_one: Pulse::new(true),
+            pulse_two: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: DMC::default(),
+            cpu_clock: CPU_CLOCK,
+            frame_steps: FRAME_STEPS,
+            five_step_mode: false,
+            frame_irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            sample_clock: 0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            last_input: 0.0,
+            last_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl APU {
+    pub fn set_region(&mut self, region: Region) {
+        self.cpu_clock = region.cpu_clock();
+        self.frame_steps = if region == Region::PAL { PAL_FRAME_STEPS } else { FRAME_STEPS };
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+    }
+
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse_one.write_register(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse_two.write_register(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write_register(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write_register(address - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write_register(address - 0x4010, value),
+            0x4015 => self.write_status(value),
+            0x4017 => self.write_frame_counter(value),
+            _ => {}
+        }
+    }
+
+    fn write_status(&mut self, value: u8) {
+        self.pulse_one.set_enabled((value & 0x01) != 0);
+        self.pulse_two.set_enabled((value & 0x02) != 0);
+        self.triangle.set_enabled((value & 0x04) != 0);
+        self.noise.set_enabled((value & 0x08) != 0);
+        self.dmc.set_enabled((value & 0x10) != 0);
+    }
+
+    fn write_frame_counter(&mut self, value: u8) {
+        self.five_step_mode = (value & 0x80) != 0;
+        self.frame_irq_inhibit = (value & 0x40) != 0;
+        if self.frame_irq_inhibit {
+            self.frame_irq = false;
+        }
+
+        self.frame_cycle = 0;
+
+        // five step mode clocks everything as soon as it's written
+        if self.five_step_mode {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+    }
+
+    // $4015, reading acknowledges the frame irq but not the dmc one
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        if self.pulse_one.is_active() {
+            status |= 0x01;
+        }
+        if self.pulse_two.is_active() {
+            status |= 0x02;
+        }
+        if self.triangle.is_active() {
+            status |= 0x04;
+        }
+        if self.noise.is_active() {
+            status |= 0x08;
+        }
+        if self.dmc.is_active() {
+            status |= 0x10;
+        }
+        if self.frame_irq {
+            status |= 0x40;
+        }
+        if self.dmc.irq() {
+            status |= 0x80;
+        }
+        status
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq()
+    }
+
+    pub fn dmc_fetch_address(&self) -> Option<u16> {
+        self.dmc.fetch_address()
+    }
+
+    pub fn dmc_fill(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_one.clock_quarter_frame();
+        self.pulse_two.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_one.clock_half_frame();
+        self.pulse_two.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    fn step_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+
+        let steps = self.frame_steps;
+        match self.frame_cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => {
+                self.clock_quarter_frame();
+            }
+            cycle if cycle == steps[1] => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            cycle if cycle == steps[3] && !self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.frame_irq_inhibit {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            cycle if cycle == steps[4] => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                self.frame_cycle = 0;
+            }
+            _ => {}
+        }
+    }
+
+    // one cpu cycle, expansion is whatever the cartridge's own sound chip is putting out
+    pub fn run(&mut self, expansion: f32) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        if self.odd_cycle {
+            self.pulse_one.clock_timer();
+            self.pulse_two.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.step_frame_counter();
+
+        self.sample_sum += self.mix() + expansion;
+        self.sample_count += 1;
+        self.sample_clock += SAMPLE_RATE;
+
+        if self.sample_clock >= self.cpu_clock {
+            self.sample_clock -= self.cpu_clock;
+
+            let input = self.sample_sum / self.sample_count as f32;
+            let output = HIGH_PASS_ALPHA * (self.last_output + input - self.last_input);
+            self.last_input = input;
+            self.last_output = output;
+            self.samples.push(output);
+
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+
+    // the usual linear approximation of the nonlinear dac, 0.0 to ~1.0
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse_one.output() + self.pulse_two.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    // hands over everything generated since last time
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::replace(&mut self.samples, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apu_tests() {
+        let mut apu = APU::default();
+
+        // enable pulse one and load its length counter
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0x3F);
+        apu.write_register(0x4002, 0xFF);
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status() & 0x01, 0x01);
+
+        // disabling clears it straight away
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(apu.read_status() & 0x01, 0x00);
+
+        // the four step sequence raises the frame irq, reading status clears it
+        apu.write_register(0x4017, 0x00);
+        for _ in 0..FRAME_STEPS[3] {
+            apu.run(0.0);
+        }
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0x00);
+
+        // inhibit stops it being raised at all
+        apu.write_register(0x4017, 0x40);
+        for _ in 0..FRAME_STEPS[3] {
+            apu.run(0.0);
+        }
+        assert!(!apu.irq());
+
+        // about a frame's worth of samples
+        let samples = apu.take_samples();
+        assert!(samples.len() > 1400);
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn region_tests() {
+        let mut apu = APU::default();
+        apu.set_region(Region::PAL);
+
+        // a PAL frame counter takes longer to get round to its irq
+        apu.write_register(0x4017, 0x00);
+        for _ in 0..FRAME_STEPS[3] {
+            apu.run(0.0);
+        }
+        assert!(!apu.irq());
+        for _ in FRAME_STEPS[3]..PAL_FRAME_STEPS[3] {
+            apu.run(0.0);
+        }
+        assert!(apu.irq());
+
+        // and a second of it is still a second of samples
+        apu.take_samples();
+        for _ in 0..Region::PAL.cpu_clock() {
+            apu.run(0.0);
+        }
+        let samples = apu.take_samples().len() as u32;
+        assert!((SAMPLE_RATE - 1..=SAMPLE_RATE + 1).contains(&samples));
+    }
+}
@@ -0,0 +1,170 @@
+// dmc.rs - the delta modulation channel at $4010-$4013
+// sample bytes come off the cpu bus, so whoever owns the bus has to feed them in
+use crate::cartridge::Region;
+
+// in cpu cycles
+pub const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_DMC_RATE_TABLE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+pub struct DMC {
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    rates: &'static [u16; 16],
+    rate: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for DMC {
+    fn default() -> Self {
+        DMC {
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            rates: &DMC_RATE_TABLE,
+            rate: DMC_RATE_TABLE[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+}
+
+impl DMC {
+    pub fn write_register(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = (value & 0x80) != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = (value & 0x40) != 0;
+                self.rate = self.rates[(value & 0x0F) as usize];
+            }
+            1 => {
+                self.output_level = value & 0x7F;
+            }
+            2 => {
+                self.sample_address = 0xC000 | ((value as u16) << 6);
+            }
+            3 => {
+                self.sample_length = ((value as u16) << 4) + 1;
+            }
+            _ => {}
+        }
+    }
+
+    // the Dendy keeps the NTSC rates despite its slower clock
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = if region == Region::PAL { &PAL_DMC_RATE_TABLE } else { &DMC_RATE_TABLE };
+    }
+
+    // writes to $4015 also acknowledge the dmc irq
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    // the memory reader wants a byte when the buffer has emptied
+    pub fn fetch_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub fn fill(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = if self.current_address == 0xFFFF {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.rate - 1;
+
+        if !self.silence {
+            if (self.shift & 1) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => {
+                    self.silence = true;
+                }
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
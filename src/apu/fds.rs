@@ -0,0 +1,300 @@
+// fds.rs - the disk system's wavetable channel
+// a 64 step 6 bit wave played back at a 12 bit pitch, bent by a modulation unit with its own table,
+// plus volume and modulation depth envelopes
+
+// full volume comes out at roughly 2.4 times a full volume apu pulse
+pub const FDS_MIX_SCALE: f32 = 0.000178;
+
+// $4089 bits 0-1, 2/2, 2/3, 2/4 and 2/5 of full volume
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+const WAVE_TABLE_SIZE: usize = 64;
+const MAX_GAIN: u8 = 32;
+
+#[derive(Default)]
+struct FDSEnvelope {
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl FDSEnvelope {
+    fn write(&mut self, value: u8) {
+        self.disabled = (value & 0x80) != 0;
+        self.increase = (value & 0x40) != 0;
+        self.speed = value & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+
+        self.timer += 1;
+        if self.timer < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.timer = 0;
+
+        if self.increase {
+            if self.gain < MAX_GAIN {
+                self.gain += 1;
+            }
+        } else if self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+pub struct FDSAudio {
+    wave_table: [u8; WAVE_TABLE_SIZE],
+    wave_write: bool,
+    wave_halt: bool,
+    wave_pos: usize,
+    wave_accumulator: u32,
+    frequency: u16,
+    envelopes_halt: bool,
+    volume: FDSEnvelope,
+    master_volume: usize,
+    envelope_speed: u8,
+
+    mod_table: [u8; WAVE_TABLE_SIZE],
+    mod_pos: usize,
+    mod_accumulator: u32,
+    mod_frequency: u16,
+    mod_halt: bool,
+    // 7 bit signed, -64 to 63
+    mod_counter: i32,
+    modulation: FDSEnvelope,
+
+    // the wave output holds while the table is being written
+    level: u8,
+}
+
+impl Default for FDSAudio {
+    fn default() -> Self {
+        FDSAudio {
+            wave_table: [0; WAVE_TABLE_SIZE],
+            wave_write: false,
+            wave_halt: false,
+            wave_pos: 0,
+            wave_accumulator: 0,
+            frequency: 0,
+            envelopes_halt: false,
+            volume: FDSEnvelope::default(),
+            master_volume: 0,
+            envelope_speed: 0,
+            mod_table: [0; WAVE_TABLE_SIZE],
+            mod_pos: 0,
+            mod_accumulator: 0,
+            mod_frequency: 0,
+            mod_halt: false,
+            mod_counter: 0,
+            modulation: FDSEnvelope::default(),
+            level: 0,
+        }
+    }
+}
+
+impl FDSAudio {
+    // $4040-$408A
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write => self.wave_table[address - 0x4040] = value & 0x3F,
+            0x4080 => self.volume.write(value),
+            0x4082 => self.frequency = (self.frequency & 0xF00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0xFF) | (((value & 0x0F) as u16) << 8);
+                self.wave_halt = (value & 0x80) != 0;
+                self.envelopes_halt = (value & 0x40) != 0;
+                if self.wave_halt {
+                    self.wave_pos = 0;
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(value),
+            0x4085 => {
+                let counter = (value & 0x7F) as i32;
+                self.mod_counter = if counter >= 64 { counter - 128 } else { counter };
+            }
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0xF00) | value as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0xFF) | (((value & 0x0F) as u16) << 8);
+                self.mod_halt = (value & 0x80) != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // the table is 32 entries each played twice, and only takes writes while halted
+            0x4088 if self.mod_halt => {
+                self.mod_table[self.mod_pos] = value & 0x07;
+                self.mod_table[(self.mod_pos + 1) % WAVE_TABLE_SIZE] = value & 0x07;
+                self.mod_pos = (self.mod_pos + 2) % WAVE_TABLE_SIZE;
+            }
+            0x4089 => {
+                self.wave_write = (value & 0x80) != 0;
+                self.master_volume = (value & 0x03) as usize;
+            }
+            0x408A => self.envelope_speed = value,
+            _ => {}
+        }
+    }
+
+    // $4040-$407F and $4090/$4092, the top two bits are open bus
+    pub fn read_register(&self, address: usize) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => Some(self.wave_table[address - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    // the modulation counter scaled by depth, applied to the pitch
+    fn modulated_pitch(&self) -> u32 {
+        let pitch = self.frequency as i32;
+        if self.mod_halt || self.mod_frequency == 0 {
+            return pitch as u32;
+        }
+
+        let mut temp = self.mod_counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (pitch + temp).max(0) as u32
+    }
+
+    fn clock_modulation(&mut self) {
+        if self.mod_halt || self.mod_frequency == 0 {
+            return;
+        }
+
+        self.mod_accumulator += self.mod_frequency as u32;
+        if self.mod_accumulator < 0x10000 {
+            return;
+        }
+        self.mod_accumulator &= 0xFFFF;
+
+        self.mod_counter = match self.mod_table[self.mod_pos] {
+            0 => self.mod_counter,
+            1 => self.mod_counter + 1,
+            2 => self.mod_counter + 2,
+            3 => self.mod_counter + 4,
+            4 => 0,
+            5 => self.mod_counter - 4,
+            6 => self.mod_counter - 2,
+            _ => self.mod_counter - 1,
+        };
+        if self.mod_counter > 63 {
+            self.mod_counter -= 128;
+        } else if self.mod_counter < -64 {
+            self.mod_counter += 128;
+        }
+        self.mod_pos = (self.mod_pos + 1) % WAVE_TABLE_SIZE;
+    }
+
+    // one cpu cycle
+    pub fn clock(&mut self) {
+        if !self.envelopes_halt && !self.wave_halt {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+
+        self.clock_modulation();
+
+        if !self.wave_halt {
+            self.wave_accumulator += self.modulated_pitch();
+            if self.wave_accumulator >= 0x10000 {
+                self.wave_accumulator &= 0xFFFF;
+                self.wave_pos = (self.wave_pos + 1) % WAVE_TABLE_SIZE;
+            }
+        }
+
+        if !self.wave_write {
+            self.level = self.wave_table[self.wave_pos];
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let gain = self.volume.gain.min(MAX_GAIN);
+        (self.level as u32 * gain as u32) as f32 * MASTER_VOLUME[self.master_volume] * FDS_MIX_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fds_audio_tests() {
+        let mut audio = FDSAudio::default();
+
+        // the table only takes writes with $4089 bit 7 set
+        audio.write_register(0x4040, 0x3F);
+        assert_eq!(audio.read_register(0x4040), Some(0x40));
+        audio.write_register(0x4089, 0x80);
+        for i in 0..WAVE_TABLE_SIZE {
+            audio.write_register(0x4040 + i, if i < 32 { 0x3F } else { 0 });
+        }
+        audio.write_register(0x4089, 0x00);
+        assert_eq!(audio.read_register(0x4040), Some(0x7F));
+
+        // direct volume, full
+        audio.write_register(0x4080, 0x80 | 0x20);
+        assert_eq!(audio.read_register(0x4090), Some(0x60));
+
+        audio.write_register(0x4082, 0x00);
+        audio.write_register(0x4083, 0x04);
+        audio.clock();
+        assert_eq!(audio.output(), 63.0 * 32.0 * FDS_MIX_SCALE);
+
+        // a pitch of $400 steps the wave every 64 cycles, so half way round it goes quiet
+        for _ in 0..32 * 64 {
+            audio.clock();
+        }
+        assert_eq!(audio.output(), 0.0);
+
+        // halting the wave puts it back at the start
+        audio.write_register(0x4083, 0x80);
+        audio.clock();
+        assert!(audio.output() > 0.0);
+
+        // envelope ramps down from 32 once every 8 * (speed + 1) * master speed cycles
+        audio.write_register(0x4083, 0x00);
+        audio.write_register(0x408A, 1);
+        audio.write_register(0x4080, 0x00);
+        for _ in 0..8 {
+            audio.clock();
+        }
+        assert_eq!(audio.read_register(0x4090), Some(0x40 | 31));
+
+        // mod table writes only land while the mod unit is halted
+        audio.write_register(0x4087, 0x80);
+        audio.write_register(0x4088, 0x01);
+        assert_eq!(audio.mod_table[0..2], [1, 1]);
+        assert_eq!(audio.mod_pos, 2);
+        audio.write_register(0x4085, 0x7F);
+        assert_eq!(audio.mod_counter, -1);
+    }
+}
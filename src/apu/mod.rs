@@ -0,0 +1,8 @@
+pub mod apu;
+pub mod dmc;
+pub mod fds;
+pub mod noise;
+pub mod pulse;
+pub mod triangle;
+pub mod units;
+pub mod vrc6;
@@ -0,0 +1,96 @@
+// noise.rs - the pseudo random noise channel at $400C-$400F
+use super::units::{Envelope, LengthCounter};
+use crate::cartridge::Region;
+
+// in cpu cycles
+pub const NOISE_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_NOISE_PERIOD_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+    shift: u16,
+    short_mode: bool,
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            // the shift register is loaded with 1 at power up
+            shift: 1,
+            short_mode: false,
+            periods: &NOISE_PERIOD_TABLE,
+            timer_period: NOISE_PERIOD_TABLE[0],
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    pub fn write_register(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.length.set_halt((value & 0x20) != 0);
+                self.envelope.write(value);
+            }
+            2 => {
+                self.short_mode = (value & 0x80) != 0;
+                self.timer_period = self.periods[(value & 0x0F) as usize];
+            }
+            3 => {
+                self.length.load(value >> 3);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    // the Dendy keeps the NTSC periods despite its slower clock
+    pub fn set_region(&mut self, region: Region) {
+        self.periods = if region == Region::PAL { &PAL_NOISE_PERIOD_TABLE } else { &NOISE_PERIOD_TABLE };
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift & 1) ^ ((self.shift >> tap) & 1);
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || (self.shift & 1) != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
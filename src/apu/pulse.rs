@@ -0,0 +1,130 @@
+// pulse.rs - the two square wave channels at $4000-$4007
+use super::units::{Envelope, LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Default)]
+pub struct Pulse {
+    // pulse one negates its sweep with ones complement, pulse two with twos
+    ones_complement: bool,
+    duty: u8,
+    duty_step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement: ones_complement,
+            ..Default::default()
+        }
+    }
+
+    pub fn write_register(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.duty = value >> 6;
+                self.length.set_halt((value & 0x20) != 0);
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = (value & 0x80) != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = (value & 0x08) != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => {
+                self.timer_period = (self.timer_period & 0x700) | value as u16;
+            }
+            3 => {
+                self.timer_period = (self.timer_period & 0xFF) | (((value & 0x07) as u16) << 8);
+                self.length.load(value >> 3);
+                self.duty_step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length.is_active()
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            if self.ones_complement {
+                self.timer_period.saturating_sub(change + 1)
+            } else {
+                self.timer_period.saturating_sub(change)
+            }
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    // the sweep unit mutes the channel even when it isn't enabled
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x7FF
+    }
+
+    // every other cpu cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.is_muted() {
+            self.timer_period = self.target_period();
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.is_muted() {
+            return 0;
+        }
+
+        if DUTY_TABLE[self.duty as usize][self.duty_step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// units.rs - the envelope and length counter shared between the apu channels
+
+pub const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    // bits 0-5 of the channel's first register
+    pub fn write(&mut self, value: u8) {
+        self.looping = (value & 0x20) != 0;
+        self.constant = (value & 0x10) != 0;
+        self.volume = value & 0x0F;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // quarter frame
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    // disabling through $4015 silences the channel straight away
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1F) as usize];
+        }
+    }
+
+    // half frame
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_tests() {
+        let mut length = LengthCounter::default();
+        length.load(1);
+        assert!(!length.is_active());
+
+        length.set_enabled(true);
+        length.load(3);
+        assert!(length.is_active());
+        length.clock();
+        length.clock();
+        assert!(!length.is_active());
+
+        let mut envelope = Envelope::default();
+        envelope.write(0x02);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        for _ in 0..3 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 14);
+
+        envelope.write(0x17);
+        assert_eq!(envelope.output(), 7);
+    }
+}
@@ -0,0 +1,206 @@
+// vrc6.rs - the konami vrc6's expansion sound, two pulses and a sawtooth
+// all three are clocked every cpu cycle and put out 0-15/0-31 levels that get mixed with the apu
+
+// roughly matches a full volume vrc6 pulse to a full volume apu pulse
+pub const VRC6_MIX_SCALE: f32 = 0.0099;
+
+#[derive(Default)]
+struct VRC6Pulse {
+    // ignore duty and output volume constantly, a crude dac
+    digitized: bool,
+    duty: u8,
+    volume: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl VRC6Pulse {
+    fn write_register(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.digitized = (value & 0x80) != 0;
+                self.duty = (value >> 4) & 0x07;
+                self.volume = value & 0x0F;
+            }
+            1 => {
+                self.period = (self.period & 0xF00) | value as u16;
+            }
+            2 => {
+                self.period = (self.period & 0xFF) | (((value & 0x0F) as u16) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = if self.step == 0 { 15 } else { self.step - 1 };
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.digitized || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct VRC6Saw {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl VRC6Saw {
+    fn write_register(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.rate = value & 0x3F;
+            }
+            1 => {
+                self.period = (self.period & 0xF00) | value as u16;
+            }
+            2 => {
+                self.period = (self.period & 0xFF) | (((value & 0x0F) as u16) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the accumulator takes the rate on every other step and resets after the 14th
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if (self.step & 1) == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[derive(Default)]
+pub struct VRC6Audio {
+    pulse_one: VRC6Pulse,
+    pulse_two: VRC6Pulse,
+    saw: VRC6Saw,
+    halt: bool,
+    freq_shift: u8,
+}
+
+impl VRC6Audio {
+    // takes registers already decoded to $9000-$B003 in vrc6a order
+    pub fn write_register(&mut self, address: usize, value: u8) {
+        let reg = address & 0x03;
+        match address & 0xF000 {
+            0x9000 if reg == 3 => {
+                self.halt = (value & 0x01) != 0;
+                self.freq_shift = if (value & 0x04) != 0 {
+                    8
+                } else if (value & 0x02) != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000 => self.pulse_one.write_register(reg, value),
+            0xA000 => self.pulse_two.write_register(reg, value),
+            0xB000 => self.saw.write_register(reg, value),
+            _ => {}
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse_one.clock(self.freq_shift);
+        self.pulse_two.clock(self.freq_shift);
+        self.saw.clock(self.freq_shift);
+    }
+
+    pub fn output(&self) -> f32 {
+        let total = self.pulse_one.output() + self.pulse_two.output() + self.saw.output();
+        total as f32 * VRC6_MIX_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vrc6_audio_tests() {
+        let mut audio = VRC6Audio::default();
+        assert_eq!(audio.output(), 0.0);
+
+        // digitized pulse just outputs its volume
+        audio.write_register(0x9000, 0x8F);
+        audio.write_register(0x9002, 0x80);
+        assert_eq!(audio.output(), 15.0 * VRC6_MIX_SCALE);
+
+        // a 50% duty pulse is high for 8 of its 16 steps
+        audio.write_register(0x9000, 0x7F);
+        audio.write_register(0x9001, 0x00);
+        let mut high = 0;
+        for _ in 0..16 {
+            audio.clock();
+            if audio.output() > 0.0 {
+                high += 1;
+            }
+        }
+        assert_eq!(high, 8);
+
+        // sawtooth climbs then resets after 14 steps
+        let mut audio = VRC6Audio::default();
+        audio.write_register(0xB000, 0x2A);
+        audio.write_register(0xB001, 0x00);
+        audio.write_register(0xB002, 0x80);
+        let mut peak = 0.0f32;
+        for _ in 0..13 {
+            audio.clock();
+            peak = peak.max(audio.output());
+        }
+        assert_eq!(peak, ((0x2A * 6) >> 3) as f32 * VRC6_MIX_SCALE);
+        audio.clock();
+        assert_eq!(audio.output(), 0.0);
+    }
+}
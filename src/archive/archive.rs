@@ -0,0 +1,92 @@
+// archive.rs - roms straight from a zip or gzip, or just the file when it's neither
+// a zip can hold readmes and boxart too, so we go for the first thing that looks like a rom
+use crate::archive::{gzip, zip};
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub const ROM_EXTENSIONS: [&str; 6] = ["nes", "unf", "unif", "fds", "nsf", "nsfe"];
+
+pub fn is_rom_name(name: &str) -> bool {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ROM_EXTENSIONS.iter().any(|rom| ext.eq_ignore_ascii_case(rom)),
+        None => false,
+    }
+}
+
+// a wanted name matches the full path in the zip or just the file name part
+fn pick_entry<'a>(names: &[&'a str], wanted: Option<&str>) -> Result<&'a str, String> {
+    match wanted {
+        Some(wanted) => names
+            .iter()
+            .find(|name| **name == wanted || Path::new(name).file_name().and_then(|f| f.to_str()) == Some(wanted))
+            .copied()
+            .ok_or_else(|| format!("no {} in the archive, it has: {}", wanted, names.join(", "))),
+        None => names.iter().find(|name| is_rom_name(name)).copied().ok_or_else(|| {
+            format!(
+                "no rom in the archive, looked for .{} files among: {}",
+                ROM_EXTENSIONS.join(", ."),
+                names.join(", ")
+            )
+        }),
+    }
+}
+
+// picks by magic number rather than extension, plenty of zips get renamed
+pub fn extract_rom(data: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, String> {
+    if zip::is_zip(&data) {
+        let entries = zip::list_entries(&data)?;
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).filter(|n| !n.ends_with('/')).collect();
+        let name = pick_entry(&names, entry)?;
+        let chosen = entries.iter().find(|e| e.name == name).unwrap();
+        zip::read_entry(&data, chosen)
+    } else if gzip::is_gzip(&data) {
+        gzip::decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+pub fn read_rom_file<P: AsRef<Path>>(path: P, entry: Option<&str>) -> Result<Vec<u8>, String> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    extract_rom(data, entry).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn is_archive(data: &[u8]) -> bool {
+    zip::is_zip(data) || gzip::is_gzip(data)
+}
+
+// a rom that came out of an archive can't be written back into it
+pub fn is_archive_file<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0; 4];
+    match fs::File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && is_archive(&magic),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_tests() {
+        assert!(is_rom_name("Game (USA).nes"));
+        assert!(is_rom_name("dir/SIDE.FDS"));
+        assert!(!is_rom_name("readme.txt"));
+        assert!(!is_rom_name("nes"));
+
+        let names = ["readme.txt", "roms/game.nes", "game.nsf"];
+        assert_eq!(pick_entry(&names, None), Ok("roms/game.nes"));
+        assert_eq!(pick_entry(&names, Some("game.nsf")), Ok("game.nsf"));
+        assert_eq!(pick_entry(&names, Some("game.nes")), Ok("roms/game.nes"));
+        assert!(pick_entry(&names, Some("other.nes")).is_err());
+        assert!(pick_entry(&["readme.txt", "box.png"], None).is_err());
+
+        // not an archive, handed back as is
+        assert_eq!(extract_rom(b"NES\x1A".to_vec(), None), Ok(b"NES\x1A".to_vec()));
+        assert!(!is_archive(b"NES\x1A"));
+    }
+}
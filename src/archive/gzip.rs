@@ -0,0 +1,89 @@
+// gzip.rs - single gzipped files, a 10 byte header with optional fields, deflate data,
+// then the crc32 and length of what came out
+use crate::archive::inflate::inflate;
+use crate::checksum;
+
+const HEADER_SIZE: usize = 10;
+const TRAILER_SIZE: usize = 8;
+const METHOD_DEFLATE: u8 = 8;
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1F, 0x8B])
+}
+
+// skips a zero terminated string, returns where it ends
+fn skip_string(data: &[u8], pos: usize) -> Result<usize, String> {
+    let len = data
+        .get(pos..)
+        .and_then(|rest| rest.iter().position(|&b| b == 0))
+        .ok_or("gzip header ends early")?;
+    Ok(pos + len + 1)
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_gzip(data) || data.len() < HEADER_SIZE + TRAILER_SIZE {
+        return Err("not a gzip file".to_string());
+    }
+    if data[2] != METHOD_DEFLATE {
+        return Err(format!("gzip compression method {} isn't deflate", data[2]));
+    }
+
+    let flags = data[3];
+    let mut pos = HEADER_SIZE;
+    if flags & FLAG_EXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or("gzip header ends early")?;
+        pos += 2 + (len[0] as usize | ((len[1] as usize) << 8));
+    }
+    if flags & FLAG_NAME != 0 {
+        pos = skip_string(data, pos)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        pos = skip_string(data, pos)?;
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+
+    let trailer = data.len() - TRAILER_SIZE;
+    if pos > trailer {
+        return Err("gzip header ends early".to_string());
+    }
+    let contents = inflate(&data[pos..trailer])?;
+
+    let crc = u32::from_le_bytes([data[trailer], data[trailer + 1], data[trailer + 2], data[trailer + 3]]);
+    let size = u32::from_le_bytes([data[trailer + 4], data[trailer + 5], data[trailer + 6], data[trailer + 7]]);
+    if checksum::crc32(&contents) != crc || contents.len() as u32 != size {
+        return Err("gzip file is corrupt, its crc doesn't match".to_string());
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip_tests() {
+        let hello = b"hello hello hello hello";
+        let mut data = vec![0x1F, 0x8B, METHOD_DEFLATE, FLAG_NAME, 0, 0, 0, 0, 0, 0xFF];
+        data.extend_from_slice(b"game.nes\0");
+        data.extend_from_slice(&[0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01]);
+        data.extend_from_slice(&checksum::crc32(hello).to_le_bytes());
+        data.extend_from_slice(&(hello.len() as u32).to_le_bytes());
+
+        assert!(is_gzip(&data));
+        assert_eq!(decompress(&data).unwrap(), hello);
+
+        let mut bad = data.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 1;
+        assert!(decompress(&bad).is_err());
+
+        assert!(decompress(b"plain old bytes").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// inflate.rs - deflate decompression (RFC 1951), all zip and gzip roms need
+// stored, fixed and dynamic huffman blocks, decoding one bit at a time since roms are small
+
+const MAX_BITS: usize = 15;
+
+// length codes 257-285 and distance codes 0-29, base values and extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// the order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos).ok_or("deflate data ends early")?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u32 << count) - 1);
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // stored blocks start on a byte boundary
+    fn align(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+// canonical huffman code, counts of codes per length and symbols in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Huffman {
+            counts: counts,
+            symbols: symbols,
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("bad huffman code in deflate data".to_string())
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let num_lengths = reader.bits(5)? as usize + 257;
+    let num_dists = reader.bits(5)? as usize + 1;
+    let num_codes = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &idx in CODE_LENGTH_ORDER.iter().take(num_codes) {
+        code_lengths[idx] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; num_lengths + num_dists];
+    let mut idx = 0;
+    while idx < lengths.len() {
+        let symbol = code_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..idx].last().ok_or("deflate repeats a length that isn't there")?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if idx + repeat > lengths.len() {
+            return Err("deflate code lengths run over".to_string());
+        }
+        for len in lengths[idx..idx + repeat].iter_mut() {
+            *len = value;
+        }
+        idx += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..num_lengths]),
+        Huffman::new(&lengths[num_lengths..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    dists: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let idx = symbol - 257;
+                if idx >= LENGTH_BASE.len() {
+                    return Err("bad length code in deflate data".to_string());
+                }
+                let len = LENGTH_BASE[idx] as usize + reader.bits(LENGTH_EXTRA[idx] as u32)? as usize;
+
+                let idx = dists.decode(reader)? as usize;
+                if idx >= DIST_BASE.len() {
+                    return Err("bad distance code in deflate data".to_string());
+                }
+                let dist = DIST_BASE[idx] as usize + reader.bits(DIST_EXTRA[idx] as u32)? as usize;
+                if dist > out.len() {
+                    return Err("deflate distance goes back too far".to_string());
+                }
+
+                // can overlap what it's writing, so byte by byte
+                let start = out.len() - dist;
+                for i in 0..len {
+                    let byte = out[start + i];
+                    out.push(byte);
+                }
+            }
+        }
+    }
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader {
+        data: data,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let pos = reader.pos;
+                if pos + 4 > data.len() {
+                    return Err("deflate data ends early".to_string());
+                }
+                let len = data[pos] as usize | ((data[pos + 1] as usize) << 8);
+                let nlen = data[pos + 2] as usize | ((data[pos + 3] as usize) << 8);
+                if len != (!nlen & 0xFFFF) {
+                    return Err("stored deflate block has a bad length".to_string());
+                }
+                let start = pos + 4;
+                let block = data.get(start..start + len).ok_or("deflate data ends early")?;
+                out.extend_from_slice(block);
+                reader.pos = start + len;
+            }
+            1 => {
+                let (lengths, dists) = fixed_tables();
+                inflate_block(&mut reader, &mut out, &lengths, &dists)?;
+            }
+            2 => {
+                let (lengths, dists) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &lengths, &dists)?;
+            }
+            _ => return Err("bad deflate block type".to_string()),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inflate_tests() {
+        // stored
+        assert_eq!(inflate(&[0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c']).unwrap(), b"abc");
+
+        // fixed huffman, "hello hello hello hello" from zlib with its header stripped
+        let fixed = [0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01];
+        assert_eq!(inflate(&fixed).unwrap(), b"hello hello hello hello");
+
+        // dynamic huffman, 300 letters from A-P
+        let dynamic = [
+            0xE5, 0xCB, 0xC1, 0x0D, 0xC0, 0x20, 0x0C, 0x03, 0xC0, 0xD9, 0xEC, 0x42,
+            0x4C, 0x02, 0x18, 0xF6, 0x9F, 0xA6, 0xEA, 0x1C, 0xFD, 0x9E, 0x74, 0x00,
+            0x9B, 0xCA, 0x4F, 0x9E, 0xB0, 0x6E, 0x75, 0xEC, 0x1A, 0x8A, 0x50, 0x4E,
+            0x53, 0x8B, 0x89, 0xE4, 0x12, 0x3D, 0xF3, 0xC3, 0x51, 0x1B, 0xBD, 0xAE,
+            0x1C, 0x27, 0x1F, 0x97, 0x1A, 0x81, 0x1F, 0xFD, 0x17,
+        ];
+        let expected: Vec<u8> = (0..300u32).map(|i| (((i * i * 3) >> 3) % 16 + 65) as u8).collect();
+        assert_eq!(inflate(&dynamic).unwrap(), expected);
+
+        assert!(inflate(&[0x07]).is_err());
+        assert!(inflate(&[]).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod archive;
+pub mod gzip;
+pub mod inflate;
+pub mod zip;
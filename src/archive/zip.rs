@@ -0,0 +1,181 @@
+// zip.rs - reading files out of a zip, the central directory at the end lists every entry
+// only stored and deflated entries, which is everything rom sets use
+use crate::archive::inflate::inflate;
+use crate::checksum;
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4B50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4B50;
+const END_OF_DIR_SIG: u32 = 0x0605_4B50;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_DIR_SIZE: usize = 22;
+// the end record can have a comment of up to 64k after it
+const MAX_COMMENT_SIZE: usize = 0xFFFF;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 0x0001;
+
+#[derive(Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: usize,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    compressed_size: usize,
+    local_offset: usize,
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | ((data[offset + 1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    read_u16(data, offset) as u32 | ((read_u16(data, offset + 2) as u32) << 16)
+}
+
+fn find_end_of_dir(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(END_OF_DIR_SIZE)?;
+    let first = last.saturating_sub(MAX_COMMENT_SIZE);
+    (first..=last).rev().find(|&pos| read_u32(data, pos) == END_OF_DIR_SIG)
+}
+
+pub fn list_entries(data: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    let end = find_end_of_dir(data).ok_or("zip has no central directory")?;
+    let count = read_u16(data, end + 10) as usize;
+    let mut pos = read_u32(data, end + 16) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if pos + CENTRAL_HEADER_SIZE > data.len() || read_u32(data, pos) != CENTRAL_HEADER_SIG {
+            return Err("zip central directory is broken".to_string());
+        }
+        let name_len = read_u16(data, pos + 28) as usize;
+        let extra_len = read_u16(data, pos + 30) as usize;
+        let comment_len = read_u16(data, pos + 32) as usize;
+        let name = data
+            .get(pos + CENTRAL_HEADER_SIZE..pos + CENTRAL_HEADER_SIZE + name_len)
+            .ok_or("zip central directory is broken")?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size: read_u32(data, pos + 24) as usize,
+            method: read_u16(data, pos + 10),
+            flags: read_u16(data, pos + 8),
+            crc32: read_u32(data, pos + 16),
+            compressed_size: read_u32(data, pos + 20) as usize,
+            local_offset: read_u32(data, pos + 42) as usize,
+        });
+        pos += CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+pub fn read_entry(data: &[u8], entry: &ZipEntry) -> Result<Vec<u8>, String> {
+    if entry.flags & FLAG_ENCRYPTED != 0 {
+        return Err(format!("{} in the zip is encrypted", entry.name));
+    }
+
+    // the local header's name and extra field can differ from the central directory's
+    let pos = entry.local_offset;
+    if pos + LOCAL_HEADER_SIZE > data.len() || read_u32(data, pos) != LOCAL_HEADER_SIG {
+        return Err(format!("zip local header for {} is broken", entry.name));
+    }
+    let start = pos + LOCAL_HEADER_SIZE + read_u16(data, pos + 26) as usize + read_u16(data, pos + 28) as usize;
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or_else(|| format!("{} runs off the end of the zip", entry.name))?;
+
+    let contents = match entry.method {
+        METHOD_STORED => compressed.to_vec(),
+        METHOD_DEFLATE => inflate(compressed)?,
+        method => return Err(format!("{} uses zip compression method {}, only stored and deflate work", entry.name, method)),
+    };
+
+    if contents.len() != entry.size || checksum::crc32(&contents) != entry.crc32 {
+        return Err(format!("{} in the zip is corrupt, its crc doesn't match", entry.name));
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // builds a zip the way most tools lay one out, entries then the central directory
+    fn build_zip(files: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for &(name, method, stored, contents) in files {
+            let offset = zip.len() as u32;
+            let crc = checksum::crc32(contents);
+
+            zip.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+            zip.extend_from_slice(&[20, 0, 0, 0]);
+            zip.extend_from_slice(&method.to_le_bytes());
+            zip.extend_from_slice(&[0; 4]);
+            zip.extend_from_slice(&crc.to_le_bytes());
+            zip.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&[0, 0]);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(stored);
+
+            central.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 4]);
+            central.extend_from_slice(&crc.to_le_bytes());
+            central.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let dir_offset = zip.len() as u32;
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(&END_OF_DIR_SIG.to_le_bytes());
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&dir_offset.to_le_bytes());
+        zip.extend_from_slice(&[0, 0]);
+        zip
+    }
+
+    #[test]
+    fn zip_tests() {
+        let hello = b"hello hello hello hello";
+        let deflated = [0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01];
+        let data = build_zip(&[
+            ("readme.txt", METHOD_STORED, b"abc", b"abc"),
+            ("dir/game.nes", METHOD_DEFLATE, &deflated, hello),
+        ]);
+        assert!(is_zip(&data));
+
+        let entries = list_entries(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "readme.txt");
+        assert_eq!(entries[1].name, "dir/game.nes");
+        assert_eq!(entries[1].size, hello.len());
+        assert_eq!(read_entry(&data, &entries[0]).unwrap(), b"abc");
+        assert_eq!(read_entry(&data, &entries[1]).unwrap(), hello);
+
+        // a flipped byte gets caught by the crc
+        let mut bad = data.clone();
+        bad[LOCAL_HEADER_SIZE + "readme.txt".len()] = b'x';
+        assert!(read_entry(&bad, &entries[0]).is_err());
+
+        assert!(list_entries(b"PK\x03\x04 not really a zip").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+// cartridge.rs - the iNES header and what it tells us about the board on the other end of the bus
+// Cartridge is the loaded form whatever the file format was, iNES and UNIF both end up here
+use crate::apu::apu::CPU_CLOCK;
+use crate::unif;
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+pub const PRG_BLOCK_SIZE: usize = 16 * 1024;
+pub const CHR_BLOCK_SIZE: usize = 8 * 1024;
+
+pub const PRG_RAM_UNIT: usize = 8 * 1024;
+pub const CHR_RAM_DEFAULT: usize = 8 * 1024;
+const FDS_PRG_RAM_SIZE: usize = 32 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleLower,
+    SingleUpper,
+    FourScreen,
+}
+
+// which console the game was made for, the cpu/ppu clocks and apu tables differ
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Region {
+    #[default]
+    NTSC,
+    PAL,
+    Dendy,
+}
+
+impl Region {
+    // for --region on the command line
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::NTSC),
+            "pal" => Some(Region::PAL),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn cpu_clock(&self) -> u32 {
+        match self {
+            Region::NTSC => CPU_CLOCK,
+            Region::PAL => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    // ppu dots per cpu cycle as a fraction, PAL's is 3.2
+    pub fn ppu_ratio(&self) -> (u32, u32) {
+        match self {
+            Region::PAL => (16, 5),
+            _ => (3, 1),
+        }
+    }
+
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            _ => 312,
+        }
+    }
+
+    // PAL's extra lines all go into a 70 line vblank, the Dendy keeps 20 and idles before it instead
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::Dendy => 291,
+            _ => 241,
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let (num, den) = self.ppu_ratio();
+        let dots_per_frame = 341.0 * self.scanlines() as f64;
+        self.cpu_clock() as f64 * num as f64 / den as f64 / dots_per_frame
+    }
+}
+
+pub struct Header {
+    pub num_prg_blocks : usize,
+    pub num_chr_blocks : usize,
+    pub has_trainer : bool,
+    pub has_battery : bool,
+    // 0 horizontal, 1 vertical, UNIF can also ask for 2/3, single screen on the first/second nametable
+    pub mirror : u8,
+    pub four_screen : bool,
+    pub mapper : u8,
+    pub prg_ram_size : usize,
+    // non zero when the board has chr ram instead of chr rom
+    pub chr_ram_size : usize,
+    pub region : Region,
+}
+
+impl Default for Header {
+    // plain NROM-256, handy when there's no cartridge to speak of (tests, tools)
+    fn default() -> Self {
+        Header {
+            num_prg_blocks : 2,
+            num_chr_blocks : 1,
+            has_trainer : false,
+            has_battery : false,
+            mirror : 0,
+            four_screen : false,
+            mapper : 0,
+            prg_ram_size : PRG_RAM_UNIT,
+            chr_ram_size : 0,
+            region : Region::NTSC,
+        }
+    }
+}
+
+impl Header {
+    // the disk system's ram adapter, it has no rom of its own beyond the 8k bios
+    pub fn fds() -> Self {
+        Header {
+            num_prg_blocks : 0,
+            num_chr_blocks : 0,
+            has_trainer : false,
+            has_battery : false,
+            mirror : 0,
+            four_screen : false,
+            mapper : 20,
+            prg_ram_size : FDS_PRG_RAM_SIZE,
+            chr_ram_size : CHR_RAM_DEFAULT,
+            region : Region::NTSC,
+        }
+    }
+
+    // what the solder pads say, mappers with their own mirroring control override this
+    pub fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            return Mirroring::FourScreen;
+        }
+        match self.mirror {
+            0 => Mirroring::Horizontal,
+            2 => Mirroring::SingleLower,
+            3 => Mirroring::SingleUpper,
+            _ => Mirroring::Vertical,
+        }
+    }
+}
+
+pub struct Cartridge {
+    pub header : Header,
+    pub prg : Vec<u8>,
+    pub chr : Vec<u8>,
+    pub trainer : Option<Vec<u8>>,
+}
+
+impl Cartridge {
+    pub fn from_ines(data : &[u8]) -> Result<Cartridge, String> {
+        if data.len() < HEADER_SIZE || !data.starts_with(b"NES\x1A") {
+            return Err("not an iNES rom".to_string());
+        }
+
+        let header = parse_header(data);
+        let mut offset = HEADER_SIZE;
+
+        let trainer_len = if header.has_trainer { TRAINER_SIZE } else { 0 };
+        let prg_len = header.num_prg_blocks * PRG_BLOCK_SIZE;
+        let chr_len = header.num_chr_blocks * CHR_BLOCK_SIZE;
+        let expected = HEADER_SIZE + trainer_len + prg_len + chr_len;
+        if data.len() < expected {
+            return Err(format!("rom is {} bytes but its header says {}", data.len(), expected));
+        }
+
+        let trainer = if header.has_trainer {
+            offset += TRAINER_SIZE;
+            Some(data[HEADER_SIZE..offset].to_vec())
+        } else {
+            None
+        };
+
+        let prg = data[offset..offset + prg_len].to_vec();
+        offset += prg_len;
+        let chr = data[offset..offset + chr_len].to_vec();
+
+        Ok(Cartridge {
+            header : header,
+            prg : prg,
+            chr : chr,
+            trainer : trainer,
+        })
+    }
+}
+
+// works out the format from the first few bytes
+pub fn load_cartridge(data : &[u8]) -> Result<Cartridge, String> {
+    if data.starts_with(b"NES\x1A") {
+        Cartridge::from_ines(data)
+    } else if unif::is_unif(data) {
+        unif::parse_unif(data)
+    } else {
+        Err("not a rom format we know (iNES or UNIF)".to_string())
+    }
+}
+
+pub fn is_nes2(mem : &[u8]) -> bool {
+    (mem[7] & 0x0C) == 0x08
+}
+
+// NES 2.0 sizes are stored as a shift count, 0 meaning none at all
+pub fn nes2_shift_size(shift : u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+pub fn parse_header(mem : &[u8]) -> Header {
+
+    let num_prg_blocks = mem[4];
+    let num_chr_blocks = mem[5];
+    let copy_byte = mem[6];
+    let mirror = copy_byte & 0x1;
+    let has_battery : bool = (copy_byte & 0x2) != 0;
+    let has_trainer : bool = (copy_byte & 0x4) != 0;
+    let four_screen : bool = (copy_byte & 0x8) != 0;
+
+    let copy_byte_2 = mem[7];
+
+    let mapper_low_nibble = copy_byte >> 4;
+    let mapper_hi_nibble = copy_byte_2 & 0xF0;
+
+    let mapper = mapper_hi_nibble | mapper_low_nibble;
+
+    // iNES stores prg ram in 8k units with 0 meaning 8k for compatibility,
+    // NES 2.0 stores volatile and battery backed sizes separately
+    let prg_ram_size = if is_nes2(mem) {
+        nes2_shift_size(mem[10] & 0x0F) + nes2_shift_size(mem[10] >> 4)
+    } else {
+        (mem[8].max(1) as usize) * PRG_RAM_UNIT
+    };
+
+    // no chr banks means the pattern tables are ram the game uploads tiles into
+    let chr_ram_size = if num_chr_blocks != 0 {
+        0
+    } else if is_nes2(mem) {
+        let size = nes2_shift_size(mem[11] & 0x0F) + nes2_shift_size(mem[11] >> 4);
+        if size == 0 { CHR_RAM_DEFAULT } else { size }
+    } else {
+        CHR_RAM_DEFAULT
+    };
+
+    // NES 2.0 has a proper timing field, iNES only a rarely set PAL bit
+    let region = if is_nes2(mem) {
+        match mem[12] & 0x03 {
+            1 => Region::PAL,
+            3 => Region::Dendy,
+            _ => Region::NTSC,
+        }
+    } else if (mem[9] & 0x01) != 0 {
+        Region::PAL
+    } else {
+        Region::NTSC
+    };
+
+    Header {
+        num_prg_blocks : num_prg_blocks as usize,
+        num_chr_blocks : num_chr_blocks as usize,
+        has_trainer : has_trainer,
+        has_battery : has_battery,
+        mirror : mirror,
+        four_screen : four_screen,
+        mapper : mapper,
+        prg_ram_size : prg_ram_size,
+        chr_ram_size : chr_ram_size,
+        region : region,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_tests() {
+        assert_eq!(Region::from_name("PAL"), Some(Region::PAL));
+        assert_eq!(Region::from_name("dendy"), Some(Region::Dendy));
+        assert_eq!(Region::from_name("secam"), None);
+
+        // the frame rates fall out of the clocks and line counts
+        assert!((Region::NTSC.frame_rate() - 60.099).abs() < 0.01);
+        assert!((Region::PAL.frame_rate() - 50.007).abs() < 0.01);
+        assert!((Region::Dendy.frame_rate() - 50.007).abs() < 0.01);
+    }
+
+    #[test]
+    fn header_tests() {
+        let mut mem = [0u8; HEADER_SIZE];
+        mem[0..4].copy_from_slice(b"NES\x1A");
+        mem[4] = 2;
+        mem[5] = 1;
+        mem[6] = 0b0001_0011;
+        mem[7] = 0x40;
+
+        let header = parse_header(&mem);
+        assert_eq!(header.num_prg_blocks, 2);
+        assert_eq!(header.num_chr_blocks, 1);
+        assert_eq!(header.mirror, 1);
+        assert!(header.has_battery);
+        assert!(!header.has_trainer);
+        assert_eq!(header.mapper, 0x41);
+        assert_eq!(header.prg_ram_size, 8 * 1024);
+        assert_eq!(header.chr_ram_size, 0);
+
+        // NES 2.0 with 8k of battery backed ram only
+        mem[7] = 0x08;
+        mem[10] = 0x70;
+        let header = parse_header(&mem);
+        assert_eq!(header.prg_ram_size, 8 * 1024);
+
+        mem[10] = 0;
+        let header = parse_header(&mem);
+        assert_eq!(header.prg_ram_size, 0);
+
+        // chr ram, 32k declared by NES 2.0 or 8k by default
+        mem[5] = 0;
+        mem[11] = 0x09;
+        let header = parse_header(&mem);
+        assert_eq!(header.chr_ram_size, 32 * 1024);
+
+        mem[7] = 0;
+        let header = parse_header(&mem);
+        assert_eq!(header.chr_ram_size, 8 * 1024);
+
+        assert_eq!(header.region, Region::NTSC);
+        mem[9] = 1;
+        assert_eq!(parse_header(&mem).region, Region::PAL);
+        mem[7] = 0x08;
+        mem[9] = 0;
+        mem[12] = 3;
+        assert_eq!(parse_header(&mem).region, Region::Dendy);
+    }
+
+    #[test]
+    fn cartridge_tests() {
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[0..4].copy_from_slice(b"NES\x1A");
+        data[4] = 1;
+        data[5] = 1;
+        data[6] = 0x04;
+        data.extend_from_slice(&[0xEE; TRAINER_SIZE]);
+        data.extend_from_slice(&[0xAA; PRG_BLOCK_SIZE]);
+
+        // a truncated dump is an error rather than a panic
+        assert!(load_cartridge(&data).is_err());
+
+        data.extend_from_slice(&[0xBB; CHR_BLOCK_SIZE]);
+        let cart = load_cartridge(&data).unwrap();
+        assert_eq!(cart.trainer, Some(vec![0xEE; TRAINER_SIZE]));
+        assert_eq!(cart.prg, vec![0xAA; PRG_BLOCK_SIZE]);
+        assert_eq!(cart.chr, vec![0xBB; CHR_BLOCK_SIZE]);
+
+        assert!(load_cartridge(b"garbage").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+// cdl.rs - code/data logger, remembers how every byte of prg and chr rom got used
+// saved in FCEUX's .cdl layout: one flag byte per prg rom byte, then one per chr rom byte,
+// offsets are into the rom itself so the same byte in different banks doesn't get mixed up
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// prg rom flags
+pub const PRG_CODE: u8 = 0x01;
+pub const PRG_DATA: u8 = 0x02;
+// which 8k slot of $8000-$FFFF it was last seen in, bits 2-3
+const PRG_BANK_MASK: u8 = 0x0C;
+// jumped to through JMP ($nnnn), or read through ($nn),Y / ($nn,X)
+pub const PRG_INDIRECT_CODE: u8 = 0x10;
+pub const PRG_INDIRECT_DATA: u8 = 0x20;
+// fetched by the dmc as sample data
+pub const PRG_PCM: u8 = 0x40;
+
+// chr rom flags
+pub const CHR_RENDERED: u8 = 0x01;
+pub const CHR_READ: u8 = 0x02;
+
+#[derive(Debug, Default)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    // chr ram boards have nothing to log on the chr side, so chr_size is 0 for them
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLog {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+        }
+    }
+
+    pub fn log_prg(&mut self, offset: usize, address: u16, flags: u8) {
+        if self.prg.is_empty() {
+            return;
+        }
+        let bank = (((address >> 13) & 3) as u8) << 2;
+        let len = self.prg.len();
+        let entry = &mut self.prg[offset % len];
+        *entry = (*entry & !PRG_BANK_MASK) | bank | flags;
+    }
+
+    pub fn log_chr(&mut self, offset: usize, flags: u8) {
+        if !self.chr.is_empty() {
+            let len = self.chr.len();
+            self.chr[offset % len] |= flags;
+        }
+    }
+
+    pub fn get_prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn get_chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    // how many prg bytes have been seen as code and as data, for a quick progress report
+    pub fn prg_coverage(&self) -> (usize, usize) {
+        let code = self.prg.iter().filter(|&&f| f & (PRG_CODE | PRG_INDIRECT_CODE) != 0).count();
+        let data = self.prg.iter().filter(|&&f| f & (PRG_DATA | PRG_INDIRECT_DATA | PRG_PCM) != 0).count();
+        (code, data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.prg.clone();
+        bytes.extend_from_slice(&self.chr);
+        bytes
+    }
+
+    // picks up where an earlier session left off, has to be from the same rom
+    pub fn load(&mut self, data: &[u8]) -> Result<(), String> {
+        let expected = self.prg.len() + self.chr.len();
+        if data.len() != expected {
+            return Err(format!("log is {} bytes, this rom needs {}", data.len(), expected));
+        }
+        let (prg, chr) = data.split_at(self.prg.len());
+        self.prg.copy_from_slice(prg);
+        self.chr.copy_from_slice(chr);
+        Ok(())
+    }
+}
+
+pub fn cdl_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("cdl")
+}
+
+// a missing log just means starting from nothing
+pub fn load_cdl_file<P: AsRef<Path>>(path: P, log: &mut CodeDataLog) -> io::Result<()> {
+    match fs::read(path) {
+        Ok(data) => log.load(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn write_cdl_file<P: AsRef<Path>>(path: P, log: &CodeDataLog) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("cdl.tmp");
+    fs::write(&tmp_path, log.to_bytes())?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cdl_tests() {
+        let mut log = CodeDataLog::new(0x8000, 0x2000);
+        log.log_prg(0x10, 0x8010, PRG_CODE);
+        log.log_prg(0x10, 0x8010, PRG_DATA);
+        log.log_prg(0x4020, 0xC020, PRG_INDIRECT_DATA);
+        // the bank bits follow wherever it was last mapped
+        log.log_prg(0x4020, 0xE020, PRG_DATA);
+        log.log_chr(0x1234, CHR_RENDERED);
+        log.log_chr(0x1234, CHR_READ);
+
+        assert_eq!(log.get_prg()[0x10], PRG_CODE | PRG_DATA);
+        assert_eq!(log.get_prg()[0x4020], (3 << 2) | PRG_INDIRECT_DATA | PRG_DATA);
+        assert_eq!(log.get_chr()[0x1234], CHR_RENDERED | CHR_READ);
+        assert_eq!(log.prg_coverage(), (1, 2));
+
+        let bytes = log.to_bytes();
+        assert_eq!(bytes.len(), 0xA000);
+        let mut reloaded = CodeDataLog::new(0x8000, 0x2000);
+        reloaded.load(&bytes).unwrap();
+        assert_eq!(reloaded.get_prg(), log.get_prg());
+        assert_eq!(reloaded.get_chr(), log.get_chr());
+
+        // a log from some other rom
+        assert!(CodeDataLog::new(0x4000, 0).load(&bytes).is_err());
+    }
+}
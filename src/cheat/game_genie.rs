@@ -0,0 +1,214 @@
+// game_genie.rs - decoding 6 and 8 letter game genie codes and patching prg rom reads with them
+// codes match on cpu address after banking, so they work the same whatever the mapper is doing
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameGenieCode {
+    pub code: String,
+    pub address: u16,
+    pub value: u8,
+    // 8 letter codes only replace the byte when it currently reads as this
+    pub compare: Option<u8>,
+    pub enabled: bool,
+}
+
+pub fn decode(code: &str) -> Result<GameGenieCode, String> {
+    let code = code.trim().to_ascii_uppercase();
+
+    let mut n = [0u16; 8];
+    for (i, letter) in code.chars().enumerate() {
+        if i >= 8 {
+            break;
+        }
+        n[i] = match LETTERS.find(letter) {
+            Some(idx) => idx as u16,
+            None => return Err(format!("'{}' isn't a game genie letter in {}", letter, code)),
+        };
+    }
+
+    let len = code.chars().count();
+    if len != 6 && len != 8 {
+        return Err(format!("game genie codes are 6 or 8 letters, {} is {}", code, len));
+    }
+
+    let address = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+
+    // the last letter's high bit moves into the compare byte for 8 letter codes
+    let (value_top, compare) = if len == 6 {
+        (n[5] & 8, None)
+    } else {
+        let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+        (n[7] & 8, Some(compare as u8))
+    };
+
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | value_top;
+
+    Ok(GameGenieCode {
+        code: code,
+        address: address,
+        value: value as u8,
+        compare: compare,
+        enabled: true,
+    })
+}
+
+#[derive(Default)]
+pub struct GameGenie {
+    codes: Vec<GameGenieCode>,
+    any_enabled: bool,
+}
+
+impl GameGenie {
+    pub fn add(&mut self, code: &str) -> Result<usize, String> {
+        let decoded = decode(code)?;
+        self.codes.push(decoded);
+        self.update_enabled();
+        Ok(self.codes.len() - 1)
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        if idx < self.codes.len() {
+            self.codes.remove(idx);
+            self.update_enabled();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.codes.clear();
+        self.update_enabled();
+    }
+
+    pub fn get_codes(&self) -> &[GameGenieCode] {
+        &self.codes
+    }
+
+    pub fn set_enabled(&mut self, idx: usize, enabled: bool) {
+        if let Some(code) = self.codes.get_mut(idx) {
+            code.enabled = enabled;
+        }
+        self.update_enabled();
+    }
+
+    pub fn toggle(&mut self, idx: usize) {
+        if let Some(code) = self.codes.get_mut(idx) {
+            code.enabled = !code.enabled;
+        }
+        self.update_enabled();
+    }
+
+    // on if anything was off, otherwise everything off
+    pub fn toggle_all(&mut self) -> bool {
+        let enable = self.codes.iter().any(|code| !code.enabled);
+        for code in self.codes.iter_mut() {
+            code.enabled = enable;
+        }
+        self.update_enabled();
+        enable
+    }
+
+    fn update_enabled(&mut self) {
+        self.any_enabled = self.codes.iter().any(|code| code.enabled);
+    }
+
+    // called on every prg rom read with what the cartridge put on the bus
+    #[inline]
+    pub fn patch(&self, address: u16, value: u8) -> u8 {
+        if !self.any_enabled {
+            return value;
+        }
+
+        for code in self.codes.iter() {
+            if code.enabled && code.address == address && code.compare.map_or(true, |c| c == value) {
+                return code.value;
+            }
+        }
+        value
+    }
+
+    // one code per line with an optional description after it, '#' starts a comment
+    // and a leading '!' loads the code switched off
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let text = fs::read_to_string(path)?;
+        let mut loaded = 0;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(rest) => (false, rest.trim_start()),
+                None => (true, line),
+            };
+
+            let code = line.split_whitespace().next().unwrap_or("");
+            match self.add(code) {
+                Ok(idx) => {
+                    self.set_enabled(idx, enabled);
+                    loaded += 1;
+                }
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+pub fn cheat_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("cht")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_tests() {
+        // super mario bros infinite lives
+        let code = decode("SXIOPO").unwrap();
+        assert_eq!(code.address, 0x91D9);
+        assert_eq!(code.value, 0xAD);
+        assert_eq!(code.compare, None);
+
+        let code = decode("yeuzugaa").unwrap();
+        assert_eq!(code.code, "YEUZUGAA");
+        assert_eq!(code.address, 0xACB3);
+        assert_eq!(code.value, 0x07);
+        assert_eq!(code.compare, Some(0x00));
+
+        assert!(decode("SXIOP").is_err());
+        assert!(decode("SXIOPB").is_err());
+
+        let mut cheats = GameGenie::default();
+        assert_eq!(cheats.patch(0x91D9, 0x01), 0x01);
+
+        let idx = cheats.add("SXIOPO").unwrap();
+        cheats.add("YEUZUGAA").unwrap();
+        assert_eq!(cheats.patch(0x91D9, 0x01), 0xAD);
+        assert_eq!(cheats.patch(0x91DA, 0x01), 0x01);
+
+        // compare byte has to match before the value is swapped in
+        assert_eq!(cheats.patch(0xACB3, 0x00), 0x07);
+        assert_eq!(cheats.patch(0xACB3, 0x05), 0x05);
+
+        cheats.toggle(idx);
+        assert_eq!(cheats.patch(0x91D9, 0x01), 0x01);
+        assert!(cheats.toggle_all());
+        assert_eq!(cheats.patch(0x91D9, 0x01), 0xAD);
+        assert!(!cheats.toggle_all());
+        assert_eq!(cheats.patch(0xACB3, 0x00), 0x00);
+    }
+}
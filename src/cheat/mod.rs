@@ -0,0 +1,2 @@
+pub mod game_genie;
+pub mod search;
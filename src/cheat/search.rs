@@ -0,0 +1,228 @@
+// search.rs - cheat search over internal and cartridge ram, and freezing what it finds
+// snapshot, let the game run, filter, repeat until only the lives counter is left
+use crate::mem_map::{INTERNAL_RAM_END, PRG_RAM_START};
+use crate::memory::RAM;
+
+const INTERNAL_RAM_LEN: usize = INTERNAL_RAM_END + 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compare {
+    EqualTo(u16),
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchResult {
+    pub address: u16,
+    pub value: u16,
+    pub previous: u16,
+}
+
+// internal ram first, then cartridge ram, as one flat block
+fn snapshot(ram: &RAM) -> Vec<u8> {
+    let mut mem = ram.get_internal_ram().to_vec();
+    mem.extend_from_slice(ram.get_prg_ram());
+    mem
+}
+
+fn offset_to_address(offset: usize) -> u16 {
+    if offset < INTERNAL_RAM_LEN {
+        offset as u16
+    } else {
+        (PRG_RAM_START + offset - INTERNAL_RAM_LEN) as u16
+    }
+}
+
+pub struct CheatSearch {
+    width: Width,
+    snapshot: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl CheatSearch {
+    pub fn new(ram: &RAM, width: Width) -> Self {
+        let mut search = CheatSearch {
+            width: width,
+            snapshot: Vec::new(),
+            candidates: Vec::new(),
+        };
+        search.reset(ram);
+        search
+    }
+
+    // everything is a candidate again
+    pub fn reset(&mut self, ram: &RAM) {
+        self.snapshot = snapshot(ram);
+
+        let prg_ram_len = self.snapshot.len() - INTERNAL_RAM_LEN;
+        self.candidates = match self.width {
+            Width::Byte => (0..self.snapshot.len()).collect(),
+            // words can't straddle internal and cartridge ram
+            Width::Word => (0..INTERNAL_RAM_LEN - 1)
+                .chain(INTERNAL_RAM_LEN..INTERNAL_RAM_LEN + prg_ram_len.saturating_sub(1))
+                .collect(),
+        };
+    }
+
+    fn value_at(&self, mem: &[u8], offset: usize) -> u16 {
+        match self.width {
+            Width::Byte => mem[offset] as u16,
+            Width::Word => (mem[offset] as u16) | ((mem[offset + 1] as u16) << 8),
+        }
+    }
+
+    // keeps the candidates whose value now compares with the last snapshot, then snapshots again
+    pub fn filter(&mut self, ram: &RAM, compare: Compare) -> usize {
+        let current = snapshot(ram);
+
+        let mut kept = Vec::with_capacity(self.candidates.len());
+        for &offset in self.candidates.iter() {
+            let now = self.value_at(&current, offset);
+            let before = self.value_at(&self.snapshot, offset);
+
+            let keep = match compare {
+                Compare::EqualTo(value) => now == value,
+                Compare::Unchanged => now == before,
+                Compare::Changed => now != before,
+                Compare::Increased => now > before,
+                Compare::Decreased => now < before,
+            };
+
+            if keep {
+                kept.push(offset);
+            }
+        }
+
+        self.candidates = kept;
+        self.snapshot = current;
+        self.candidates.len()
+    }
+
+    pub fn count(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn get_width(&self) -> Width {
+        self.width
+    }
+
+    pub fn results(&self, ram: &RAM) -> Vec<SearchResult> {
+        let current = snapshot(ram);
+        self.candidates
+            .iter()
+            .map(|&offset| SearchResult {
+                address: offset_to_address(offset),
+                value: self.value_at(&current, offset),
+                previous: self.value_at(&self.snapshot, offset),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Freeze {
+    pub address: u16,
+    pub value: u16,
+    pub width: Width,
+}
+
+// addresses held at a value, written back once a frame
+#[derive(Default)]
+pub struct Freezes {
+    frozen: Vec<Freeze>,
+}
+
+impl Freezes {
+    // freezing an address again just changes its value
+    pub fn freeze(&mut self, address: u16, value: u16, width: Width) {
+        self.unfreeze(address);
+        self.frozen.push(Freeze {
+            address: address,
+            value: value,
+            width: width,
+        });
+    }
+
+    pub fn unfreeze(&mut self, address: u16) -> bool {
+        let before = self.frozen.len();
+        self.frozen.retain(|freeze| freeze.address != address);
+        self.frozen.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.frozen.clear();
+    }
+
+    pub fn get_frozen(&self) -> &[Freeze] {
+        &self.frozen
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frozen.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Header;
+
+    #[test]
+    fn search_tests() {
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        test_memory.write_mem_value(0x0033, 5);
+        test_memory.write_mem_value(0x6100, 5);
+
+        let mut search = CheatSearch::new(&test_memory, Width::Byte);
+        assert_eq!(search.count(), 2048 + 8192);
+
+        search.filter(&test_memory, Compare::EqualTo(5));
+        assert_eq!(search.count(), 2);
+
+        // lose a life
+        test_memory.write_mem_value(0x0033, 4);
+        search.filter(&test_memory, Compare::Decreased);
+        let results = search.results(&test_memory);
+        assert_eq!(
+            results,
+            vec![SearchResult {
+                address: 0x0033,
+                value: 4,
+                previous: 4
+            }]
+        );
+
+        // 16 bit values are little endian
+        test_memory.write_mem_value(0x6200, 0x34);
+        test_memory.write_mem_value(0x6201, 0x12);
+        let mut search = CheatSearch::new(&test_memory, Width::Word);
+        assert_eq!(search.count(), 2047 + 8191);
+        search.filter(&test_memory, Compare::EqualTo(0x1234));
+        assert_eq!(search.results(&test_memory)[0].address, 0x6200);
+
+        test_memory.write_mem_value(0x6201, 0x13);
+        assert_eq!(search.filter(&test_memory, Compare::Increased), 1);
+        assert_eq!(search.filter(&test_memory, Compare::Changed), 0);
+
+        // frozen values get put back every frame
+        test_memory.get_freezes().freeze(0x0033, 9, Width::Byte);
+        test_memory.get_freezes().freeze(0x6200, 0xBEEF, Width::Word);
+        test_memory.apply_freezes();
+        assert_eq!(test_memory.read_mem_value(0x0033), 9);
+        assert_eq!(test_memory.read_mem_address(0x6200), 0xBEEF);
+
+        assert!(test_memory.get_freezes().unfreeze(0x0033));
+        test_memory.write_mem_value(0x0033, 1);
+        test_memory.apply_freezes();
+        assert_eq!(test_memory.read_mem_value(0x0033), 1);
+    }
+}
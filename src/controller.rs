@@ -0,0 +1,71 @@
+// controller.rs - the standard joypad, read a bit at a time through $4016/$4017
+
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+#[derive(Default)]
+pub struct Controller {
+    buttons: u8,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        if pressed {
+            self.buttons |= button;
+        } else {
+            self.buttons &= !button;
+        }
+    }
+
+    pub fn get_buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    // bit 0 of a $4016 write, while high the shift register keeps reloading
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = (value & 1) != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    // after all eight buttons an official pad reads back 1s
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 1;
+        }
+
+        let bit = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller_tests() {
+        let mut pad = Controller::default();
+        pad.set_button(BUTTON_A, true);
+        pad.set_button(BUTTON_START, true);
+        pad.set_button(BUTTON_RIGHT, true);
+
+        pad.write_strobe(1);
+        assert_eq!(pad.read(), 1);
+        assert_eq!(pad.read(), 1);
+        pad.write_strobe(0);
+
+        let bits: Vec<u8> = (0..10).map(|_| pad.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+}
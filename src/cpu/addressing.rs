@@ -206,7 +206,7 @@ pub fn indexed_indirect(
 ) -> u8 {
     let addr = operand.wrapping_add(x_val);
     let table_addr = memory.read_mem_address(addr as u16);
-    let mem_value = memory.read_mem_value(table_addr);
+    let mem_value = memory.read_mem_indirect(table_addr);
 
     in_val = match_on_op(in_val, mem_value, op, status_flag) as u8;
 
@@ -223,7 +223,7 @@ pub fn indirect_indexed(
 ) -> u8 {
     let table_addr = memory.read_mem_address(operand as u16);
     let addr: u16 = table_addr + y_val as u16;
-    let mem_value = memory.read_mem_value(addr as u16);
+    let mem_value = memory.read_mem_indirect(addr as u16);
 
     in_val = match_on_op(in_val, mem_value, op, status_flag);
 
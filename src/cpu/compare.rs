@@ -106,7 +106,7 @@ pub fn comp_value_indexed_indirect(
     cycles: &mut u8,
 ) {
     let mem_addr = mem.read_mem_address((addr + x_reg) as u16);
-    let mem_val = mem.read_mem_value(mem_addr);
+    let mem_val = mem.read_mem_indirect(mem_addr);
     set_flags(status_flags, acc, mem_val);
     *pc_reg += 2;
     *cycles = 6;
@@ -122,7 +122,7 @@ pub fn comp_value_indirect_indexed(
     cycles: &mut u8,
 ) {
     let mem_addr = mem.read_mem_address(addr as u16);
-    let mem_val = mem.read_mem_value(mem_addr + y_reg as u16);
+    let mem_val = mem.read_mem_indirect(mem_addr + y_reg as u16);
     set_flags(status_flags, acc, mem_val);
     *pc_reg += 2;
     *cycles = 5;
@@ -19,6 +19,7 @@ pub fn jump_indirect(pc_reg: &mut u16, indirect_addr: u16, ram: &mut RAM, cycles
         addr = msb | addr;
     }
 
+    ram.log_indirect_code(addr);
     *pc_reg = addr;
     *cycles = 5;
 }
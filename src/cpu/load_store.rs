@@ -109,7 +109,7 @@ pub fn indirect_x_load(
 ) -> u8 {
     let addr = operand.wrapping_add(x_val);
     let table_addr = memory.read_mem_address(addr as u16);
-    let mem_value = memory.read_mem_value(table_addr);
+    let mem_value = memory.read_mem_indirect(table_addr);
     set_flags(mem_value, status_flag);
 
     *pc_reg += 2;
@@ -142,7 +142,7 @@ pub fn indirect_y_load(
     cycles: &mut u8,
 ) -> u8 {
     let table_addr = memory.read_mem_address(operand as u16);
-    let mem_value = memory.read_mem_value(table_addr.wrapping_add(y_val as u16));
+    let mem_value = memory.read_mem_indirect(table_addr.wrapping_add(y_val as u16));
     set_flags(mem_value, status_flag);
 
     *pc_reg += 2;
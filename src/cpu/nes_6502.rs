@@ -7,6 +7,7 @@ use super::asl;
 use super::branch;
 use super::compare;
 use super::flags;
+use super::flags::{BREAK_CMD_BIT, INTERRUPT_DISABLE_BIT};
 use super::increment_decrement;
 use super::jumps;
 use super::load_store;
@@ -18,6 +19,21 @@ use super::ror;
 use super::sub;
 use crate::memory::RAM;
 
+// bytes in the instruction, opcode included, going by the addressing mode in the opcode's bits
+// (the low two bits pick the group, bits 2-4 the mode)
+pub fn instruction_length(opcode: u8) -> u16 {
+    let mode = (opcode >> 2) & 7;
+    match (opcode & 3, mode) {
+        // brk, rti, rts and the implied/accumulator ones
+        (0, 0) if opcode == 0x20 => 3,
+        (0, 0) if opcode < 0x80 => 1,
+        (0, 2) | (0, 6) | (2, 2) | (2, 4) | (2, 6) => 1,
+        (_, 3) | (_, 7) => 3,
+        (1, 6) | (3, 6) => 3,
+        _ => 2,
+    }
+}
+
 #[derive(Debug)]
 pub struct Nes6502 {
     accumulator: u8,
@@ -27,6 +43,7 @@ pub struct Nes6502 {
     stack_pointer: u8,
     pc_counter: u16,
     cycles_until_next: u8,
+    stall_cycles: u16,
     total_cycles: u128,
     nmi_vector: u16,
     irq_vector: u16
@@ -42,6 +59,7 @@ impl Nes6502 {
             stack_pointer: 0xFD,
             pc_counter: 0,
             cycles_until_next: 0,
+            stall_cycles: 0,
             total_cycles: 7,
             nmi_vector: 0,
             irq_vector: 0,
@@ -57,10 +75,46 @@ impl Nes6502 {
         self.pc_counter = ram.read_mem_address(0xFFFC);
     }
 
+    pub fn get_pc(&self) -> u16 {
+        self.pc_counter
+    }
+
+    // jsr into a routine from outside the program, its rts lands on return_to
+    // this is how the nsf player calls INIT and PLAY
+    pub fn call(&mut self, address: u16, a: u8, x: u8, return_to: u16, ram: &mut RAM) {
+        ram.push_address_on_stack(&mut self.stack_pointer, return_to - 1);
+        self.accumulator = a;
+        self.x = x;
+        self.y = 0;
+        flags::set_interrupt_disable(&mut self.status_flags);
+        self.pc_counter = address;
+        self.cycles_until_next = 0;
+    }
+
     pub fn run(&mut self, ram: &mut RAM) {
         if self.cycles_until_next == 0 {
+            // dma holds the cpu off the bus
+            self.stall_cycles += ram.take_dma_stall(self.total_cycles % 2 == 1);
+            if self.stall_cycles > 0 {
+                self.stall_cycles -= 1;
+                self.total_cycles += 1;
+                return;
+            }
+
+            // nmi can't be masked and wins over irq
+            if ram.take_nmi() {
+                self.interrupt(0xFFFA, ram);
+                return;
+            }
+
+            if ram.irq_pending() && (self.status_flags & INTERRUPT_DISABLE_BIT) == 0 {
+                self.interrupt(0xFFFE, ram);
+                return;
+            }
+
             // get next opcode
-            let opcode = ram.read_mem_value(self.pc_counter);
+            let opcode = ram.fetch_opcode(self.pc_counter);
+            ram.log_instruction(self.pc_counter, instruction_length(opcode));
             self.decode_instruction(opcode, ram);
         } else {
                 self.cycles_until_next -= 1;
@@ -68,6 +122,16 @@ impl Nes6502 {
         }
     }
 
+    // hardware interrupts push pc and status (with break clear) then jump through the vector
+    fn interrupt(&mut self, vector: u16, ram: &mut RAM) {
+        ram.push_address_on_stack(&mut self.stack_pointer, self.pc_counter);
+        let status = (self.status_flags & !BREAK_CMD_BIT) | 0b0010_0000;
+        ram.push_value_on_stack(&mut self.stack_pointer, status);
+        flags::set_interrupt_disable(&mut self.status_flags);
+        self.pc_counter = ram.read_mem_address(vector);
+        self.cycles_until_next = 7;
+    }
+
     fn decode_instruction(&mut self, opcode: u8, ram: &mut RAM) {
         //println!("{:#x} {:#04x} A:{:#04x} X:{:#04x} Y:{:#04x} P:{:#04x} SP:{:#04x} cycles:{}", self.pc_counter, opcode, self.accumulator, self.x, self.y, self.status_flags, self.stack_pointer, self.total_cycles);
         match opcode {
@@ -1758,3 +1822,16 @@ impl Nes6502 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_length_tests() {
+        // brk, jsr, rts, lda #, lda abs, lda (zp),y, lda abs,y, asl a, nop, jmp ($nnnn)
+        let opcodes = [0x00, 0x20, 0x60, 0xA9, 0xAD, 0xB1, 0xB9, 0x0A, 0xEA, 0x6C];
+        let lengths: Vec<u16> = opcodes.iter().map(|&op| instruction_length(op)).collect();
+        assert_eq!(lengths, vec![1, 3, 1, 2, 3, 2, 3, 1, 1, 3]);
+    }
+}
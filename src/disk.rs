@@ -0,0 +1,208 @@
+// disk.rs - famicom disk system .fds images
+// the drive reads a side as one long stream, so each side is kept with the gaps, block start marks
+// and crcs a real disk has between blocks, and those get stripped again when the image is written back
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SIDE_SIZE: usize = 65500;
+pub const FDS_HEADER_SIZE: usize = 16;
+// the ram adapter's bios sits in the top 8k of the cpu's address space
+pub const BIOS_SIZE: usize = 8 * 1024;
+
+// a bit more than the payload once gaps, marks and crcs are added, so files can be appended
+const RAW_SIDE_SIZE: usize = 80_000;
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const BLOCK_START_MARK: u8 = 0x80;
+const CRC_SIZE: usize = 2;
+
+const DISK_INFO_BLOCK: u8 = 1;
+const FILE_AMOUNT_BLOCK: u8 = 2;
+const FILE_HEADER_BLOCK: u8 = 3;
+const FILE_DATA_BLOCK: u8 = 4;
+
+const DISK_VERIFICATION: &[u8] = b"*NINTENDO-HVC*";
+
+#[derive(Default)]
+pub struct DiskImage {
+    sides: Vec<Vec<u8>>,
+    // fwNES images start with a 16 byte header, keep it that way when writing back
+    has_header: bool,
+}
+
+// either a headered fwNES image or raw sides that start with the disk info block
+pub fn is_fds(data: &[u8]) -> bool {
+    data.starts_with(b"FDS\x1A")
+        || (data.len() >= SIDE_SIZE
+            && data[0] == DISK_INFO_BLOCK
+            && data[1..].starts_with(DISK_VERIFICATION))
+}
+
+// length of the block starting at data[0], file data blocks need the size from the header before it
+fn block_len(data: &[u8], file_size: usize) -> Option<usize> {
+    match *data.first()? {
+        DISK_INFO_BLOCK => Some(56),
+        FILE_AMOUNT_BLOCK => Some(2),
+        FILE_HEADER_BLOCK => Some(16),
+        FILE_DATA_BLOCK => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP];
+    let mut pos = 0;
+    let mut file_size = 0;
+
+    while let Some(len) = block_len(&side[pos..], file_size) {
+        if pos + len > side.len() {
+            break;
+        }
+        let block = &side[pos..pos + len];
+        if block[0] == FILE_HEADER_BLOCK {
+            file_size = block[13] as usize | ((block[14] as usize) << 8);
+        }
+
+        raw.push(BLOCK_START_MARK);
+        raw.extend_from_slice(block);
+        // we never report crc errors, so these are just placeholders
+        raw.extend_from_slice(&[0; CRC_SIZE]);
+        raw.extend_from_slice(&[0; BLOCK_GAP]);
+        pos += len;
+    }
+
+    if raw.len() < RAW_SIDE_SIZE {
+        raw.resize(RAW_SIDE_SIZE, 0);
+    }
+    raw
+}
+
+fn strip_gaps(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut pos = 0;
+    let mut file_size = 0;
+
+    loop {
+        while pos < raw.len() && raw[pos] != BLOCK_START_MARK {
+            pos += 1;
+        }
+        pos += 1;
+        if pos >= raw.len() {
+            break;
+        }
+
+        let len = match block_len(&raw[pos..], file_size) {
+            Some(len) if pos + len <= raw.len() => len,
+            _ => break,
+        };
+        let block = &raw[pos..pos + len];
+        if block[0] == FILE_HEADER_BLOCK {
+            file_size = block[13] as usize | ((block[14] as usize) << 8);
+        }
+
+        side.extend_from_slice(block);
+        pos += len + CRC_SIZE;
+    }
+
+    side.resize(SIDE_SIZE, 0);
+    side
+}
+
+impl DiskImage {
+    pub fn parse(data: &[u8]) -> Result<DiskImage, String> {
+        let has_header = data.starts_with(b"FDS\x1A");
+        let body = if has_header { &data[FDS_HEADER_SIZE.min(data.len())..] } else { data };
+
+        let num_sides = body.len() / SIDE_SIZE;
+        if num_sides == 0 {
+            return Err(format!("{} bytes is too short for a disk side", data.len()));
+        }
+
+        let sides = body
+            .chunks_exact(SIDE_SIZE)
+            .map(add_gaps)
+            .collect();
+
+        Ok(DiskImage {
+            sides: sides,
+            has_header: has_header,
+        })
+    }
+
+    pub fn num_sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    // the side as the drive sees it, gaps and all
+    pub fn get_side(&self, side: usize) -> &[u8] {
+        &self.sides[side]
+    }
+
+    pub fn get_side_mut(&mut self, side: usize) -> &mut [u8] {
+        &mut self.sides[side]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(FDS_HEADER_SIZE + self.sides.len() * SIDE_SIZE);
+        if self.has_header {
+            data.extend_from_slice(b"FDS\x1A");
+            data.push(self.sides.len() as u8);
+            data.resize(FDS_HEADER_SIZE, 0);
+        }
+        for side in self.sides.iter() {
+            data.extend_from_slice(&strip_gaps(side));
+        }
+        data
+    }
+}
+
+// same dance as battery saves, write elsewhere first so a crash can't eat the disk
+pub fn write_disk_image<P: AsRef<Path>>(path: P, disk: &DiskImage) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("fds.tmp");
+    fs::write(&tmp_path, disk.to_bytes())?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_tests() {
+        let mut side = vec![0u8; SIDE_SIZE];
+        side[0] = DISK_INFO_BLOCK;
+        side[1..15].copy_from_slice(DISK_VERIFICATION);
+        side[56] = FILE_AMOUNT_BLOCK;
+        side[57] = 1;
+        side[58] = FILE_HEADER_BLOCK;
+        // 3 byte file
+        side[58 + 13] = 3;
+        side[74] = FILE_DATA_BLOCK;
+        side[75..78].copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+        assert!(is_fds(&side));
+        let disk = DiskImage::parse(&side).unwrap();
+        assert_eq!(disk.num_sides(), 1);
+
+        // the first block starts after the leading gap and its mark
+        let raw = disk.get_side(0);
+        assert_eq!(raw.len(), RAW_SIDE_SIZE);
+        assert_eq!(raw[LEADING_GAP], BLOCK_START_MARK);
+        assert_eq!(raw[LEADING_GAP + 1], DISK_INFO_BLOCK);
+        let second = LEADING_GAP + 1 + 56 + CRC_SIZE + BLOCK_GAP;
+        assert_eq!(raw[second], BLOCK_START_MARK);
+        assert_eq!(raw[second + 1], FILE_AMOUNT_BLOCK);
+
+        assert_eq!(disk.to_bytes(), side);
+
+        let mut headered = b"FDS\x1A\x01".to_vec();
+        headered.resize(FDS_HEADER_SIZE, 0);
+        headered.extend_from_slice(&side);
+        let disk = DiskImage::parse(&headered).unwrap();
+        assert_eq!(disk.to_bytes(), headered);
+
+        assert!(DiskImage::parse(&side[..100]).is_err());
+    }
+}
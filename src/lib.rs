@@ -1,4 +1,19 @@
+pub mod apu;
+pub mod archive;
+pub mod cartridge;
+pub mod cdl;
+pub mod checksum;
+pub mod cheat;
+pub mod controller;
 pub mod cpu;
+pub mod disk;
+pub mod mapper;
 pub mod mem_map;
 pub mod memory;
+pub mod nsf;
 pub mod ppu;
+pub mod romdb;
+pub mod save;
+pub mod unif;
+pub mod watch;
+pub mod wav;
@@ -19,11 +19,11 @@
 
 use std::env;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::Path;
 
 extern crate sdl2;
-use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::{Color, PixelFormatEnum};
@@ -32,19 +32,39 @@ use sdl2::video::{self, Window, WindowBuildError, WindowBuilder, WindowContext};
 use sdl2::Sdl;
 use std::time::{Duration, Instant};
 
+use nes_emu::apu::apu::{CPU_CLOCK, SAMPLE_RATE};
+use nes_emu::archive::archive;
+use nes_emu::cartridge::{load_cartridge, Cartridge, Region};
+use nes_emu::cdl;
+use nes_emu::cheat::game_genie;
+use nes_emu::cheat::search::{CheatSearch, Compare, Width};
+use nes_emu::controller::*;
 use nes_emu::cpu::nes_6502::Nes6502;
+use nes_emu::disk::{self, DiskImage};
+use nes_emu::mapper::fds::FDS;
 use nes_emu::memory::RAM;
+use nes_emu::nsf::nsf::{self, NSF};
+use nes_emu::nsf::player::NSFPlayer;
+use nes_emu::ppu::pallette::{self, Pallette};
 use nes_emu::ppu::ppu::PPU;
 use nes_emu::ppu::ppu::output_image;
+use nes_emu::romdb::{self, GameEntry, RomChecksums};
+use nes_emu::save;
+use nes_emu::watch::Watchpoint;
+use nes_emu::wav;
 
 const WIDTH: u32 = 256;
 const HEIGHT: u32 = 240;
 
-const SECONDS_PER_CLOCK: f32 = 1.0 / 1_790_000.0; // 1.79 MHz freq
-const NANOS_PER_CLOCK: u128 = (SECONDS_PER_CLOCK * 1_000_000_000.0) as u128;
+// don't let audio get more than a few frames behind
+const MAX_QUEUED_AUDIO_BYTES : u32 = (SAMPLE_RATE / 10) * 4;
 
-// NES was 60 FPS
-const NANOS_PER_FRAME : u128 = 1_000_000_000 / 60;
+// flush battery ram roughly every 5 seconds so a crash doesn't lose much progress
+const SAVE_INTERVAL_FRAMES : u32 = 300;
+
+// how much of an NSF track goes into a wav when the file doesn't give a length
+const DEFAULT_TRACK_MS : u32 = 150_000;
+const DEFAULT_FADE_MS : u32 = 5_000;
 
 fn main() {
     // todo
@@ -52,18 +72,213 @@ fn main() {
     // read rom into mem
     // start cpu execution
     let args: Vec<String> = env::args().collect();
-    let rom_path = &args[1];
 
-    let rom_data = load_binary(rom_path);
-    // parse header info
-    let (prg_blocks, chr_blocks, has_trainer, mirror, mapper) = parse_header(&rom_data);
+    // --pal swaps in a community palette, --export-pal(-full) writes out whichever one is in use
+    // as 64 colors or with all the emphasis sets, handy for converting one to the other.
+    // none of it needs a rom or a window, exporting without a rom just writes the file and stops
+    let pal = match option_value(&args, "--pal") {
+        Some(path) => match pallette::load_pal_file(path) {
+            Ok(pal) => pal,
+            Err(e) => {
+                println!("couldn't load {}: {}", path, e);
+                Pallette::default()
+            }
+        },
+        None => Pallette::default(),
+    };
+    let mut exported = false;
+    for &(option, with_emphasis) in [("--export-pal", false), ("--export-pal-full", true)].iter() {
+        if let Some(path) = option_value(&args, option) {
+            exported = true;
+            if let Err(e) = pallette::write_pal_file(path, &pal, with_emphasis) {
+                println!("couldn't write {}: {}", path, e);
+            }
+        }
+    }
+
+    let rom_path = match args.get(1) {
+        Some(path) if !path.starts_with("--") => path,
+        _ => {
+            if !exported {
+                println!("usage: nes_emu ROM [options]");
+            }
+            return;
+        }
+    };
+
+    // zips and gzips get unpacked here, --entry picks a file when the zip has several roms
+    let rom_data = match archive::read_rom_file(rom_path, option_value(&args, "--entry").map(|e| e.as_str())) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    if nsf::is_nsf(&rom_data) {
+        run_nsf(&args, &rom_data);
+        return;
+    }
+
+    let mut ram: RAM = if disk::is_fds(&rom_data) {
+        // nintendo's bios isn't ours to ship, the user has to point us at their copy
+        let bios_path = match option_value(&args, "--bios") {
+            Some(path) => path,
+            None => {
+                println!("disk images need the disk system bios, e.g. --bios disksys.rom");
+                return;
+            }
+        };
+        let bios = match load_binary(bios_path) {
+            Ok(bios) if bios.len() == disk::BIOS_SIZE => bios,
+            Ok(bios) => {
+                println!("{} is {} bytes, the disk system bios is {}", bios_path, bios.len(), disk::BIOS_SIZE);
+                return;
+            }
+            Err(e) => {
+                println!("couldn't load {}: {}", bios_path, e);
+                return;
+            }
+        };
+        let disk = match DiskImage::parse(&rom_data) {
+            Ok(disk) => disk,
+            Err(e) => {
+                println!("couldn't load {}: {}", rom_path, e);
+                return;
+            }
+        };
+        println!("{} disk sides, F8 flips to the next one", disk.num_sides());
+        RAM::new_fds(&bios, disk)
+    } else {
+        // iNES or UNIF
+        let mut cart = match load_cartridge(&rom_data) {
+            Ok(cart) => cart,
+            Err(e) => {
+                println!("couldn't load {}: {}", rom_path, e);
+                return;
+            }
+        };
+
+        // --no-db trusts the header as is, --rom-info just says what we found and stops
+        let sums = romdb::checksums(&cart);
+        let game = if has_flag(&args, "--no-db") { None } else { romdb::lookup(&sums) };
+        let corrections = match game {
+            Some(game) => romdb::correct_header(game, &mut cart.header),
+            None => Vec::new(),
+        };
+
+        // --region ntsc|pal|dendy beats both the header and the database
+        if let Some(name) = option_value(&args, "--region") {
+            match Region::from_name(name) {
+                Some(region) => cart.header.region = region,
+                None => println!("unknown region '{}', expected ntsc, pal or dendy", name),
+            }
+        }
+
+        if has_flag(&args, "--rom-info") {
+            print_rom_info(&cart, &sums, game, &corrections);
+            return;
+        }
+        if let Some(game) = game {
+            println!("{} ({:?})", game.title, game.region);
+        }
+        for correction in corrections.iter() {
+            println!("header corrected: {}", correction);
+        }
+
+        match RAM::from_cartridge(&cart) {
+            Ok(ram) => ram,
+            Err(e) => {
+                println!("couldn't load {}: {}", rom_path, e);
+                return;
+            }
+        }
+    };
+
+    let sav_path = save::sav_path(rom_path);
+    if ram.has_battery() {
+        if let Err(e) = save::load_battery_ram(&sav_path, &mut ram) {
+            println!("couldn't load {}: {}", sav_path.display(), e);
+        }
+    }
+
+    // --cdl logs how the rom gets used into <rom>.cdl, carrying on from any log already there
+    let cdl_path = cdl::cdl_path(rom_path);
+    if has_flag(&args, "--cdl") {
+        ram.start_cdl();
+        if let Some(log) = ram.get_cdl() {
+            if let Err(e) = cdl::load_cdl_file(&cdl_path, log) {
+                println!("couldn't load {}: {}", cdl_path.display(), e);
+            }
+        }
+    }
 
-    let mut ram: RAM = RAM::new(prg_blocks as usize, chr_blocks as usize, mapper, mirror);
-    ram.load_rom(rom_data);
+    let cheat_path = game_genie::cheat_path(rom_path);
+    if cheat_path.exists() {
+        match ram.get_game_genie().load_file(&cheat_path) {
+            Ok(count) => println!("loaded {} cheats from {}", count, cheat_path.display()),
+            Err(e) => println!("couldn't load {}: {}", cheat_path.display(), e),
+        }
+    }
+
+    let mut extra_args = args.iter().skip(2);
+    while let Some(arg) = extra_args.next() {
+        match arg.as_str() {
+            "--watch" => {
+                // r|w|x[:ppu]:ADDR[-END][=VALUE]
+                let spec = match extra_args.next() {
+                    Some(spec) => spec,
+                    None => {
+                        println!("--watch needs a watchpoint, e.g. w:6000-60FF");
+                        return;
+                    }
+                };
+                match Watchpoint::parse(spec) {
+                    Ok(point) => {
+                        ram.get_watchpoints().add(point);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+            "--cheat" => {
+                let code = match extra_args.next() {
+                    Some(code) => code,
+                    None => {
+                        println!("--cheat needs a game genie code");
+                        return;
+                    }
+                };
+                if let Err(e) = ram.get_game_genie().add(code) {
+                    println!("{}", e);
+                }
+            }
+            "--freeze" => {
+                // ADDR=VALUE in hex, a 4 digit value freezes a 16 bit word
+                let spec = match extra_args.next() {
+                    Some(spec) => spec,
+                    None => {
+                        println!("--freeze needs ADDR=VALUE, e.g. 075A=09");
+                        return;
+                    }
+                };
+                match parse_freeze(spec) {
+                    Some((address, value, width)) => ram.get_freezes().freeze(address, value, width),
+                    None => println!("bad freeze '{}', expected e.g. 075A=09", spec),
+                }
+            }
+            // already picked up when loading the rom
+            "--bios" | "--entry" | "--region" | "--pal" | "--export-pal" | "--export-pal-full" => {
+                extra_args.next();
+            }
+            "--no-db" | "--rom-info" | "--cdl" => {}
+            other => println!("ignoring unknown option {}", other),
+        }
+    }
 
     
     // do SDL init stuff
     let mut sdl_context = sdl2::init().unwrap();
+    let audio_queue = init_audio(&mut sdl_context);
     let win = init_window(&mut sdl_context, WIDTH, HEIGHT);
     let unrapped = win.unwrap();
 
@@ -81,14 +296,21 @@ fn main() {
         );
     }
 
-    // set ppu status
-    ram.write_mem_value(0x2002, 0b10100000);
-
     let mut cpu: Nes6502 = Nes6502::new();
     cpu.init(&mut ram);
     
     let mut ppu: PPU = PPU::default();
 
+    // clock speed, frame rate and how many ppu dots to a cpu cycle all depend on the console
+    let region = ram.get_region();
+    ppu.set_region(region);
+    let nanos_per_clock = 1_000_000_000 / region.cpu_clock() as u128;
+    let nanos_per_frame = (1_000_000_000.0 / region.frame_rate()) as u128;
+    let (ppu_dots, ppu_cycles) = region.ppu_ratio();
+    let mut ppu_clock = 0;
+
+    ppu.set_pallette(pal);
+
     // create pixel data
     let mut pixData: Box<[u8]> = vec![0; WIDTH as usize * HEIGHT as usize * 4].into_boxed_slice();
     let mut frame_index = 0;
@@ -97,6 +319,10 @@ fn main() {
     let mut timeTaken: u32 = 0;
 
     let mut frame_time : u128 = 0;
+    let mut frame_count : u32 = 0;
+
+    // F2 starts a cheat search, F3/F4/F6/F7 narrow it down
+    let mut cheat_search : Option<CheatSearch> = None;
 
     'running: loop {
         //beginning of loop
@@ -105,15 +331,109 @@ fn main() {
 
         for event in event_pump.poll_iter() {
             match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => ram.get_watchpoints().resume(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => {
+                    let enabled = ram.get_game_genie().toggle_all();
+                    println!("cheats {}", if enabled { "on" } else { "off" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    if let Some(fds) = ram.get_disk_system() {
+                        // save before the side leaves the drive
+                        flush_disk(rom_path, fds);
+                        let side = fds.switch_side();
+                        println!("inserting disk side {}", side);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    let search = CheatSearch::new(&ram, Width::Byte);
+                    println!("cheat search started, {} candidates", search.count());
+                    cheat_search = Some(search);
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ Keycode::F3),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(key @ Keycode::F4),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(key @ Keycode::F6),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(key @ Keycode::F7),
+                    ..
+                } => {
+                    if let Some(search) = &mut cheat_search {
+                        let compare = match key {
+                            Keycode::F3 => Compare::Decreased,
+                            Keycode::F4 => Compare::Increased,
+                            Keycode::F6 => Compare::Changed,
+                            _ => Compare::Unchanged,
+                        };
+                        let count = search.filter(&ram, compare);
+                        println!("{:?}: {} candidates", compare, count);
+                        for result in search.results(&ram).iter().take(16) {
+                            println!("  {:#06x} = {:#04x}", result.address, result.value);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(button) = key_to_button(key) {
+                        ram.get_controller(0).set_button(button, true);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(button) = key_to_button(key) {
+                        ram.get_controller(0).set_button(button, false);
+                    }
+                }
                 _ => {}
             }
         }
 
+        // sat on a watchpoint until F5
+        if ram.get_watchpoints().is_paused() {
+            if let Some(hit) = ram.get_watchpoints().take_hit() {
+                println!(
+                    "watchpoint {} hit: {:?} {:?} {:#06x} = {:#04x}, F5 to resume",
+                    hit.id, hit.bus, hit.access, hit.address, hit.value
+                );
+            }
+            ::std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
         cpu.run(&mut ram);
+        ram.run_cycle();
 
-        // 3 cycles for every cpu one
-        for i in 0..3 {
+        // 3 dots for every cpu cycle, 3.2 on PAL
+        ppu_clock += ppu_dots;
+        while ppu_clock >= ppu_cycles {
             ppu.run(&mut ram);
+            ppu_clock -= ppu_cycles;
         }
 
        
@@ -124,7 +444,7 @@ fn main() {
 
         frame_time += cycle_time;
 
-        if frame_time > NANOS_PER_FRAME  {
+        if frame_time > nanos_per_frame  {
             frame_time = 0;
             expand_vram(ppu.get_output_image(), &mut pixData);
             let texRef = &mut frameBuffers[frame_index];
@@ -133,24 +453,233 @@ fn main() {
             canvas.copy(&texRef, None, None);
 
             canvas.present();
+
+            let samples = ram.get_apu().take_samples();
+            if let Some(queue) = &audio_queue {
+                if queue.size() < MAX_QUEUED_AUDIO_BYTES {
+                    queue.queue(&samples);
+                }
+            }
+
+            ram.apply_freezes();
+
+            frame_count = frame_count.wrapping_add(1);
+            if ram.has_battery() && ram.is_prg_ram_dirty() && frame_count % SAVE_INTERVAL_FRAMES == 0 {
+                flush_save(&sav_path, &mut ram);
+            }
             continue;
         }
 
-        if cycle_time > NANOS_PER_CLOCK {
+        if cycle_time > nanos_per_clock {
             continue;
         }
 
-        let sleepAmount = (NANOS_PER_CLOCK - cycle_time) as u32;
+        let sleepAmount = (nanos_per_clock - cycle_time) as u32;
         //println!("{}", sleepAmount);
-        ::std::thread::sleep(Duration::new(0, sleepAmount)); // 1.79 MHz freq
+        ::std::thread::sleep(Duration::new(0, sleepAmount));
+    }
+
+    if ram.has_battery() && ram.is_prg_ram_dirty() {
+        flush_save(&sav_path, &mut ram);
+    }
+
+    if let Some(fds) = ram.get_disk_system() {
+        flush_disk(rom_path, fds);
+    }
+
+    if let Some(log) = ram.get_cdl() {
+        let (code, data) = log.prg_coverage();
+        println!("cdl: {} code and {} data bytes of {} logged", code, data, log.get_prg().len());
+        if let Err(e) = cdl::write_cdl_file(&cdl_path, log) {
+            println!("couldn't write {}: {}", cdl_path.display(), e);
+        }
+    }
+}
+
+// music rips get their own loop, no ppu and no controller, just tracks
+// --track N picks where to start, --wav OUT [--seconds S] renders it to a file instead of playing
+fn run_nsf(args: &[String], data: &[u8]) {
+    let nsf = match NSF::parse(data) {
+        Ok(nsf) => nsf,
+        Err(e) => {
+            println!("couldn't load {}: {}", args[1], e);
+            return;
+        }
+    };
+    println!("{} - {} ({}), {} tracks", nsf.title, nsf.artist, nsf.copyright, nsf.total_songs);
+    if (nsf.expansion & !(nsf::EXPANSION_VRC6 | nsf::EXPANSION_FDS)) != 0 {
+        println!("expansion audio {:#04x} is only partly supported, some channels will be missing", nsf.expansion);
+    }
+
+    let mut player = NSFPlayer::new(nsf);
+    if let Some(track) = option_value(args, "--track").and_then(|t| t.parse::<u8>().ok()) {
+        player.start_track(track.saturating_sub(1));
+    }
+
+    if let Some(wav_path) = option_value(args, "--wav") {
+        let seconds = option_value(args, "--seconds").and_then(|s| s.parse::<f32>().ok());
+        let length = seconds
+            .map(|s| (s * 1000.0) as u32)
+            .or(player.track_length_ms())
+            .unwrap_or(DEFAULT_TRACK_MS);
+        let fade = player.track_fade_ms().unwrap_or(DEFAULT_FADE_MS);
+
+        let samples = player.render(length, fade);
+        match wav::write_wav(wav_path, &samples, SAMPLE_RATE) {
+            Ok(()) => println!("wrote {} seconds to {}", samples.len() as u32 / SAMPLE_RATE, wav_path),
+            Err(e) => println!("couldn't write {}: {}", wav_path, e),
+        }
+        return;
+    }
+
+    let mut sdl_context = sdl2::init().unwrap();
+    let audio_queue = match init_audio(&mut sdl_context) {
+        Some(queue) => queue,
+        None => {
+            println!("no audio device, try --wav instead");
+            return;
+        }
+    };
+    // only there to get key presses
+    let _window = init_window(&mut sdl_context, WIDTH, HEIGHT).unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    println!("{}, left/right to change track", player.get_nsf().track_name(player.get_track()));
+
+    'playing: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'playing,
+                Event::KeyDown {
+                    keycode: Some(key @ Keycode::Left),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(key @ Keycode::Right),
+                    ..
+                } => {
+                    if key == Keycode::Right {
+                        player.next_track();
+                    } else {
+                        player.prev_track();
+                    }
+                    println!("{}", player.get_nsf().track_name(player.get_track()));
+                }
+                _ => {}
+            }
+        }
+
+        // the audio queue sets the pace, top it up a frame at a time
+        if audio_queue.size() >= MAX_QUEUED_AUDIO_BYTES {
+            ::std::thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        for _ in 0..CPU_CLOCK / 60 {
+            player.run_cycle();
+        }
+        audio_queue.queue(&player.take_samples());
     }
 }
 
-fn load_binary<P: AsRef<Path>>(path: P) -> Box<[u8]> {
-    let mut file = fs::File::open(path).unwrap();
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
+
+fn print_rom_info(cart: &Cartridge, sums: &RomChecksums, game: Option<&GameEntry>, corrections: &[String]) {
+    let header = &cart.header;
+    println!("crc32    {:08x}", sums.crc32);
+    println!("sha1     {}", sums.sha1);
+    println!("prg      {}k", cart.prg.len() / 1024);
+    println!("chr      {}k{}", cart.chr.len() / 1024, if header.chr_ram_size != 0 { " (ram)" } else { "" });
+    println!("mapper   {}", header.mapper);
+    println!("mirror   {:?}", header.mirroring());
+    println!("battery  {}", header.has_battery);
+    println!("region   {:?}", header.region);
+
+    match game {
+        Some(game) => println!("game     {}", game.title),
+        None => println!("game     not in the database"),
+    }
+    for correction in corrections {
+        println!("fixed    {}", correction);
+    }
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    let idx = args.iter().position(|arg| arg == name)?;
+    args.get(idx + 1)
+}
+
+fn parse_freeze(spec: &str) -> Option<(u16, u16, Width)> {
+    let mut parts = spec.splitn(2, '=');
+    let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let value_text = parts.next()?;
+    let value = u16::from_str_radix(value_text, 16).ok()?;
+    let width = if value_text.len() > 2 { Width::Word } else { Width::Byte };
+    Some((address, value, width))
+}
+
+fn flush_save(sav_path: &Path, ram: &mut RAM) {
+    if let Err(e) = save::flush_battery_ram(sav_path, ram) {
+        println!("couldn't write {}: {}", sav_path.display(), e);
+    }
+}
+
+// games save straight onto the disk, so that gets written back over the image
+// or next to it as a plain .fds when the image came out of an archive
+fn flush_disk(rom_path: &str, fds: &mut FDS) {
+    if !fds.is_disk_dirty() {
+        return;
+    }
+    let disk_path = if archive::is_archive_file(rom_path) {
+        Path::new(rom_path).with_extension("fds")
+    } else {
+        Path::new(rom_path).to_path_buf()
+    };
+    match disk::write_disk_image(&disk_path, fds.get_disk()) {
+        Ok(()) => fds.clear_disk_dirty(),
+        Err(e) => println!("couldn't write {}: {}", disk_path.display(), e),
+    }
+}
+
+fn load_binary<P: AsRef<Path>>(path: P) -> io::Result<Box<[u8]>> {
+    let mut file = fs::File::open(path)?;
     let mut file_buf = Vec::new();
-    file.read_to_end(&mut file_buf);
-    file_buf.into_boxed_slice()
+    file.read_to_end(&mut file_buf)?;
+    Ok(file_buf.into_boxed_slice())
+}
+
+// no audio device isn't fatal, we just run silent
+fn init_audio(context: &mut Sdl) -> Option<AudioQueue<f32>> {
+    let audio_subsystem = context.audio().ok()?;
+    let spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: Some(1024),
+    };
+
+    let queue = audio_subsystem.open_queue::<f32, _>(None, &spec).ok()?;
+    queue.resume();
+    Some(queue)
+}
+
+// pad one on the keyboard
+fn key_to_button(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::X => Some(BUTTON_A),
+        Keycode::Z => Some(BUTTON_B),
+        Keycode::RShift => Some(BUTTON_SELECT),
+        Keycode::Return => Some(BUTTON_START),
+        Keycode::Up => Some(BUTTON_UP),
+        Keycode::Down => Some(BUTTON_DOWN),
+        Keycode::Left => Some(BUTTON_LEFT),
+        Keycode::Right => Some(BUTTON_RIGHT),
+        _ => None,
+    }
 }
 
 fn init_window(context: &mut Sdl, width: u32, height: u32) -> Result<Window, WindowBuildError> {
@@ -180,22 +709,3 @@ fn expand_vram(mem : &[(u8, u8, u8)], pixData : &mut [u8]) {
         }
     }
 }
-
-fn parse_header( mem : &Box<[u8]>) -> (u8, u8, bool, u8, u8) {
-
-    let num_prg_blocks = mem[4];
-    let num_chr_blocks = mem[5];
-    let copy_byte = mem[6];
-    let has_trainer : bool = (copy_byte & 0x2) != 0;
-    let mirror = (copy_byte & 0x1);
-    let ignore_mirror : bool = (copy_byte & 0x8) != 0;
-    
-    let copy_byte_2 = mem[7];
-    
-    let mapper_low_nibble = (copy_byte >> 4);
-    let mapper_hi_nibble = copy_byte_2 & 0xF0;
-
-    let mapper = mapper_hi_nibble | mapper_low_nibble; 
-
-    (num_prg_blocks, num_chr_blocks, has_trainer, mirror, mapper)
-}
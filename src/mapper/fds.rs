@@ -0,0 +1,432 @@
+// fds.rs - the famicom disk system's ram adapter
+// 32k of prg ram at $6000-$DFFF, the bios at $E000, 8k of chr ram, a cpu cycle irq timer,
+// the disk drive and the wavetable channel, all hanging off registers at $4020-$4092
+use super::mapper::{Mapper, PrgAddr};
+use crate::apu::apu::CPU_CLOCK;
+use crate::apu::fds::FDSAudio;
+use crate::cartridge::Mirroring;
+use crate::disk::DiskImage;
+
+// cpu cycles for the motor to get the head back to the start of the disk
+const SPIN_UP_CYCLES: u32 = 50_000;
+// the drive moves a byte roughly every 150 cpu cycles (~96kbit/s)
+const BYTE_CYCLES: u32 = 150;
+// how long a disk stays out when flipping sides, the bios has to see the drive empty first
+const SWAP_CYCLES: u32 = CPU_CLOCK / 2;
+
+pub struct FDS {
+    disk: DiskImage,
+    side: Option<usize>,
+    next_side: Option<usize>,
+    swap_delay: u32,
+    disk_dirty: bool,
+
+    // $4023
+    disk_regs_enabled: bool,
+    sound_regs_enabled: bool,
+
+    // $4020-$4022
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+
+    // $4025
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    mirroring: Mirroring,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    disk_irq: bool,
+    transfer_complete: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    position: usize,
+    delay: u32,
+    read_data: u8,
+    write_data: u8,
+    external: u8,
+
+    audio: FDSAudio,
+}
+
+impl FDS {
+    // starts with side A in the drive, if there is one
+    pub fn new(disk: DiskImage) -> Self {
+        let side = if disk.num_sides() > 0 { Some(0) } else { None };
+        FDS {
+            disk: disk,
+            side: side,
+            next_side: None,
+            swap_delay: 0,
+            disk_dirty: false,
+            disk_regs_enabled: false,
+            sound_regs_enabled: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            mirroring: Mirroring::Horizontal,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            read_data: 0,
+            write_data: 0,
+            external: 0,
+            audio: FDSAudio::default(),
+        }
+    }
+
+    pub fn get_disk(&self) -> &DiskImage {
+        &self.disk
+    }
+
+    pub fn num_sides(&self) -> usize {
+        self.disk.num_sides()
+    }
+
+    // None while the drive is empty
+    pub fn get_side(&self) -> Option<usize> {
+        self.side
+    }
+
+    pub fn eject(&mut self) {
+        self.side = None;
+        self.next_side = None;
+    }
+
+    pub fn insert(&mut self, side: usize) {
+        if side < self.disk.num_sides() {
+            self.side = Some(side);
+            self.next_side = None;
+        }
+    }
+
+    // ejects now and puts the next side in once the bios has had time to notice
+    pub fn switch_side(&mut self) -> usize {
+        let next = match self.side.or(self.next_side) {
+            Some(side) => (side + 1) % self.disk.num_sides().max(1),
+            None => 0,
+        };
+        self.side = None;
+        self.next_side = Some(next);
+        self.swap_delay = SWAP_CYCLES;
+        next
+    }
+
+    pub fn is_disk_dirty(&self) -> bool {
+        self.disk_dirty
+    }
+
+    pub fn clear_disk_dirty(&mut self) {
+        self.disk_dirty = false;
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.motor_on = (value & 0x01) != 0;
+        self.reset_transfer = (value & 0x02) != 0;
+        self.read_mode = (value & 0x04) != 0;
+        self.mirroring = if (value & 0x08) != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        self.crc_control = (value & 0x10) != 0;
+        self.disk_ready = (value & 0x40) != 0;
+        self.disk_irq_enabled = (value & 0x80) != 0;
+        self.disk_irq = false;
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled || !self.disk_regs_enabled {
+            return;
+        }
+
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            if self.irq_repeat {
+                self.irq_counter = self.irq_reload;
+            } else {
+                self.irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        if self.swap_delay > 0 {
+            self.swap_delay -= 1;
+            if self.swap_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+
+        let side = match self.side {
+            Some(side) if self.motor_on => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+
+        // head parked at the end, rewind to the start
+        if self.end_of_head {
+            self.delay = SPIN_UP_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut need_irq = self.disk_irq_enabled;
+
+        if self.read_mode {
+            let data = self.disk.get_side(side)[self.position];
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the block start mark, the bios only wants to hear about what follows it
+                self.gap_ended = true;
+                need_irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+            if !self.disk_ready {
+                data = 0;
+            }
+            self.disk.get_side_mut(side)[self.position] = data;
+            self.disk_dirty = true;
+            self.gap_ended = false;
+        }
+
+        self.position += 1;
+        if self.position >= self.disk.get_side(side).len() {
+            self.motor_on = false;
+            if need_irq {
+                self.disk_irq = true;
+            }
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for FDS {
+    fn map_prg(&self, address: usize) -> PrgAddr {
+        match address {
+            0x6000..=0xDFFF => PrgAddr::Ram(address - 0x6000),
+            _ => PrgAddr::Rom(address - 0xE000),
+        }
+    }
+
+    fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | ((value as u16) << 8),
+            0x4022 => {
+                self.irq_repeat = (value & 0x01) != 0;
+                self.irq_enabled = (value & 0x02) != 0 && self.disk_regs_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_regs_enabled = (value & 0x01) != 0;
+                self.sound_regs_enabled = (value & 0x02) != 0;
+                if !self.disk_regs_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024..=0x4026 if !self.disk_regs_enabled => {}
+            0x4024 => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => self.write_control(value),
+            0x4026 => self.external = value,
+            0x4040..=0x408A if self.sound_regs_enabled => self.audio.write_register(address, value),
+            _ => {}
+        }
+    }
+
+    fn read_register(&mut self, address: usize) -> Option<u8> {
+        match address {
+            0x4030 => {
+                let mut value = 0;
+                if self.timer_irq {
+                    value |= 0x01;
+                }
+                if self.transfer_complete {
+                    value |= 0x02;
+                }
+                if self.end_of_head {
+                    value |= 0x40;
+                }
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                Some(value)
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                Some(self.read_data)
+            }
+            0x4032 => {
+                // not inserted, not ready, write protected
+                let value = match self.side {
+                    None => 0x07,
+                    Some(_) if !self.scanning => 0x02,
+                    Some(_) => 0x00,
+                };
+                Some(0x40 | value)
+            }
+            // battery's fine, and whatever the expansion port is reflecting back
+            0x4033 => Some(0x80 | (self.external & 0x7F)),
+            0x4040..=0x4092 => self.audio.read_register(address),
+            _ => None,
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn disk_system(&mut self) -> Option<&mut FDS> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::SIDE_SIZE;
+
+    fn test_disk() -> DiskImage {
+        let mut side = vec![0u8; SIDE_SIZE * 2];
+        side[0] = 1;
+        side[1..15].copy_from_slice(b"*NINTENDO-HVC*");
+        side[56] = 2;
+        DiskImage::parse(&side).unwrap()
+    }
+
+    #[test]
+    fn fds_tests() {
+        let mut fds = FDS::new(test_disk());
+        assert_eq!(fds.map_prg(0x6000), PrgAddr::Ram(0));
+        assert_eq!(fds.map_prg(0xDFFF), PrgAddr::Ram(0x7FFF));
+        assert_eq!(fds.map_prg(0xFFFC), PrgAddr::Rom(0x1FFC));
+
+        // timer needs the disk registers enabled, counts down from the reload value
+        fds.write_register(0x4020, 2);
+        fds.write_register(0x4022, 0x02);
+        assert!(!fds.irq_enabled);
+        fds.write_register(0x4023, 0x01);
+        fds.write_register(0x4022, 0x02);
+        fds.clock_timer();
+        fds.clock_timer();
+        assert!(!fds.irq());
+        fds.clock_timer();
+        assert!(fds.irq());
+        assert_eq!(fds.read_register(0x4030), Some(0x41));
+        assert!(!fds.irq());
+
+        // motor on, read mode, looking for a block
+        assert_eq!(fds.read_register(0x4032), Some(0x42));
+        fds.write_register(0x4025, 0x80 | 0x40 | 0x04 | 0x01);
+        let mut irqs = 0;
+        while irqs < 2 {
+            fds.clock_drive();
+            if fds.irq() {
+                irqs += 1;
+                let value = fds.read_register(0x4031).unwrap();
+                // the start mark is swallowed, the first byte is the block type
+                if irqs == 1 {
+                    assert_eq!(value, 1);
+                } else {
+                    assert_eq!(value, b'*');
+                }
+            }
+        }
+        assert_eq!(fds.read_register(0x4032), Some(0x40));
+
+        // writing marks the disk as changed
+        fds.write_register(0x4025, 0x80 | 0x40 | 0x01);
+        fds.write_register(0x4024, 0x55);
+        while !fds.irq() {
+            fds.clock_drive();
+        }
+        assert!(fds.is_disk_dirty());
+
+        // side switching empties the drive for a while first
+        assert_eq!(fds.switch_side(), 1);
+        assert_eq!(fds.get_side(), None);
+        assert_eq!(fds.read_register(0x4032), Some(0x47));
+        for _ in 0..SWAP_CYCLES {
+            fds.clock_drive();
+        }
+        assert_eq!(fds.get_side(), Some(1));
+    }
+}
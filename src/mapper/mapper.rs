@@ -0,0 +1,88 @@
+// mapper.rs - what the board does with addresses between the bus and the rom/ram chips
+// mappers only translate addresses into offsets, RAM still owns the actual bytes
+use super::fds::FDS;
+use super::mmc2::MMC2;
+use super::nrom::NROM;
+use super::vrc6::VRC6;
+use crate::cartridge::{Header, Mirroring};
+use crate::disk::DiskImage;
+
+pub const PRG_BANK_8K: usize = 8 * 1024;
+pub const PRG_BANK_16K: usize = 16 * 1024;
+pub const CHR_BANK_1K: usize = 1024;
+pub const CHR_BANK_4K: usize = 4 * 1024;
+
+// where a cpu access in $6000-$FFFF ends up
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PrgAddr {
+    Rom(usize),
+    Ram(usize),
+    OpenBus,
+}
+
+pub trait Mapper {
+    // $6000-$FFFF
+    fn map_prg(&self, address: usize) -> PrgAddr;
+
+    // pattern table address to offset in chr rom/ram
+    fn map_chr(&self, address: usize) -> usize {
+        address
+    }
+
+    // every cpu write from $4020 up, whether or not something else is mapped there too
+    fn write_register(&mut self, _address: usize, _value: u8) {}
+
+    // $4020-$5FFF, None leaves the bus floating
+    fn read_register(&mut self, _address: usize) -> Option<u8> {
+        None
+    }
+
+    // every pattern table read the ppu makes, after the byte has been fetched
+    fn ppu_read(&mut self, _address: usize) {}
+
+    fn mirroring(&self) -> Mirroring;
+
+    // once per cpu cycle, for boards with their own timers
+    fn clock_cpu(&mut self) {}
+
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // expansion audio, on the same scale as the apu's own mix
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    // the disk system's drive, for the frontend to swap sides and save the disk
+    fn disk_system(&mut self) -> Option<&mut FDS> {
+        None
+    }
+}
+
+// $6000-$7FFF is work ram on most boards
+pub fn map_prg_ram(address: usize) -> PrgAddr {
+    PrgAddr::Ram(address - 0x6000)
+}
+
+pub fn create_mapper(header: &Header) -> Result<Box<dyn Mapper>, String> {
+    let prg_len = header.num_prg_blocks * PRG_BANK_16K;
+    let mirroring = header.mirroring();
+
+    // mmc2 and mmc4 fix the top of $8000-$FFFF to the last banks, so they need at least 32k to do it
+    if (header.mapper == 9 || header.mapper == 10) && prg_len < 0x8000 {
+        return Err(format!("mapper {} needs at least 32k of prg, the header says {}k", header.mapper, prg_len / 1024));
+    }
+
+    let mapper: Box<dyn Mapper> = match header.mapper {
+        0 => Box::new(NROM::new(prg_len, mirroring)),
+        9 => Box::new(MMC2::new_mmc2(prg_len)),
+        10 => Box::new(MMC2::new_mmc4(prg_len)),
+        // an empty drive, RAM::new_fds puts the disk in
+        20 => Box::new(FDS::new(DiskImage::default())),
+        24 => Box::new(VRC6::new_vrc6a(prg_len)),
+        26 => Box::new(VRC6::new_vrc6b(prg_len)),
+        other => return Err(format!("mapper {} isn't supported", other)),
+    };
+    Ok(mapper)
+}
@@ -0,0 +1,161 @@
+// mmc2.rs - mappers 9 (MMC2, Punch-Out!!) and 10 (MMC4, Fire Emblem)
+// each half of the pattern table has two chr banks, and a latch picks between them
+// whenever the ppu fetches tile $FD or $FE from that half
+use super::mapper::{map_prg_ram, Mapper, PrgAddr, CHR_BANK_4K, PRG_BANK_16K, PRG_BANK_8K};
+use crate::cartridge::Mirroring;
+
+const LATCH_FD: usize = 0;
+const LATCH_FE: usize = 1;
+
+pub struct MMC2 {
+    // MMC4 swaps 16k of prg instead of 8k and has looser latch 0 addresses
+    is_mmc4: bool,
+    prg_len: usize,
+    prg_bank: usize,
+    // [half][latch]
+    chr_banks: [[usize; 2]; 2],
+    latches: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl MMC2 {
+    fn new(prg_len: usize, is_mmc4: bool) -> Self {
+        MMC2 {
+            is_mmc4: is_mmc4,
+            prg_len: prg_len,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE, LATCH_FE],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    pub fn new_mmc2(prg_len: usize) -> Self {
+        MMC2::new(prg_len, false)
+    }
+
+    pub fn new_mmc4(prg_len: usize) -> Self {
+        MMC2::new(prg_len, true)
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        if self.is_mmc4 {
+            PRG_BANK_16K
+        } else {
+            PRG_BANK_8K
+        }
+    }
+}
+
+impl Mapper for MMC2 {
+    fn map_prg(&self, address: usize) -> PrgAddr {
+        if let 0x6000..=0x7FFF = address {
+            return map_prg_ram(address);
+        }
+
+        if self.prg_len == 0 {
+            return PrgAddr::OpenBus;
+        }
+
+        let bank_size = self.prg_bank_size();
+        let num_banks = self.prg_len / bank_size;
+        let window = (address - 0x8000) / bank_size;
+
+        // the first window switches, everything after it is fixed to the last banks
+        let bank = if window == 0 {
+            self.prg_bank % num_banks
+        } else {
+            let windows = 0x8000 / bank_size;
+            num_banks - (windows - window)
+        };
+
+        PrgAddr::Rom(bank * bank_size + (address % bank_size))
+    }
+
+    fn map_chr(&self, address: usize) -> usize {
+        let half = (address >> 12) & 1;
+        let bank = self.chr_banks[half][self.latches[half]];
+        bank * CHR_BANK_4K + (address % CHR_BANK_4K)
+    }
+
+    fn write_register(&mut self, address: usize, value: u8) {
+        let value = (value & 0x1F) as usize;
+        match address {
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][LATCH_FD] = value,
+            0xC000..=0xCFFF => self.chr_banks[0][LATCH_FE] = value,
+            0xD000..=0xDFFF => self.chr_banks[1][LATCH_FD] = value,
+            0xE000..=0xEFFF => self.chr_banks[1][LATCH_FE] = value,
+            0xF000..=0xFFFF => {
+                self.mirroring = if (value & 1) == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // the latch flips after the fetch, so the tile that trips it still comes from the old bank
+    fn ppu_read(&mut self, address: usize) {
+        match address {
+            0x0FD8 => self.latches[0] = LATCH_FD,
+            0x0FE8 => self.latches[0] = LATCH_FE,
+            0x0FD9..=0x0FDF if self.is_mmc4 => self.latches[0] = LATCH_FD,
+            0x0FE9..=0x0FEF if self.is_mmc4 => self.latches[0] = LATCH_FE,
+            0x1FD8..=0x1FDF => self.latches[1] = LATCH_FD,
+            0x1FE8..=0x1FEF => self.latches[1] = LATCH_FE,
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mmc2_tests() {
+        // 128k prg, 16 8k banks
+        let mut mmc2 = MMC2::new_mmc2(128 * 1024);
+        mmc2.write_register(0xA000, 3);
+        assert_eq!(mmc2.map_prg(0x8000), PrgAddr::Rom(3 * PRG_BANK_8K));
+        assert_eq!(mmc2.map_prg(0xA000), PrgAddr::Rom(13 * PRG_BANK_8K));
+        assert_eq!(mmc2.map_prg(0xFFFF), PrgAddr::Rom(16 * PRG_BANK_8K - 1));
+
+        mmc2.write_register(0xB000, 4);
+        mmc2.write_register(0xC000, 5);
+        mmc2.write_register(0xD000, 6);
+        mmc2.write_register(0xE000, 7);
+        assert_eq!(mmc2.map_chr(0x0010), 5 * CHR_BANK_4K + 0x10);
+        assert_eq!(mmc2.map_chr(0x1010), 7 * CHR_BANK_4K + 0x10);
+
+        // latch 0 only trips on the exact address for MMC2
+        mmc2.ppu_read(0x0FD9);
+        assert_eq!(mmc2.map_chr(0x0010), 5 * CHR_BANK_4K + 0x10);
+        mmc2.ppu_read(0x0FD8);
+        assert_eq!(mmc2.map_chr(0x0010), 4 * CHR_BANK_4K + 0x10);
+        mmc2.ppu_read(0x1FDB);
+        assert_eq!(mmc2.map_chr(0x1010), 6 * CHR_BANK_4K + 0x10);
+        mmc2.ppu_read(0x1FEF);
+        assert_eq!(mmc2.map_chr(0x1010), 7 * CHR_BANK_4K + 0x10);
+
+        mmc2.write_register(0xF000, 1);
+        assert_eq!(mmc2.mirroring(), Mirroring::Horizontal);
+
+        // MMC4 banks 16k at a time and takes the whole row for latch 0
+        let mut mmc4 = MMC2::new_mmc4(128 * 1024);
+        mmc4.write_register(0xA000, 2);
+        assert_eq!(mmc4.map_prg(0xBFFF), PrgAddr::Rom(3 * PRG_BANK_16K - 1));
+        assert_eq!(mmc4.map_prg(0xC000), PrgAddr::Rom(7 * PRG_BANK_16K));
+        mmc4.write_register(0xB000, 1);
+        mmc4.ppu_read(0x0FDE);
+        assert_eq!(mmc4.map_chr(0x0000), CHR_BANK_4K);
+        assert_eq!(mmc4.map_prg(0x6000), PrgAddr::Ram(0));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod fds;
+pub mod mapper;
+pub mod mmc2;
+pub mod nrom;
+pub mod nsf;
+pub mod vrc6;
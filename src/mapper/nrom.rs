@@ -0,0 +1,31 @@
+// nrom.rs - mapper 0, no banking, 16k boards see their prg twice
+use super::mapper::{map_prg_ram, Mapper, PrgAddr};
+use crate::cartridge::Mirroring;
+
+pub struct NROM {
+    prg_len: usize,
+    mirroring: Mirroring,
+}
+
+impl NROM {
+    pub fn new(prg_len: usize, mirroring: Mirroring) -> Self {
+        NROM {
+            prg_len: prg_len,
+            mirroring: mirroring,
+        }
+    }
+}
+
+impl Mapper for NROM {
+    fn map_prg(&self, address: usize) -> PrgAddr {
+        match address {
+            0x6000..=0x7FFF => map_prg_ram(address),
+            _ if self.prg_len == 0 => PrgAddr::OpenBus,
+            _ => PrgAddr::Rom((address - 0x8000) % self.prg_len),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,91 @@
+// nsf.rs - the board an NSF player provides, 4k prg banks at $5FF8-$5FFF, 8k of ram at $6000,
+// any expansion sound the rip asks for, and a tiny driver routine for INIT and PLAY to return into
+use super::mapper::{Mapper, PrgAddr};
+use crate::apu::fds::FDSAudio;
+use crate::apu::vrc6::VRC6Audio;
+use crate::cartridge::Mirroring;
+
+pub const NSF_BANK_SIZE: usize = 4 * 1024;
+
+// INIT and PLAY rts into a JMP to itself here, which is how the player knows they're done
+pub const DRIVER_ADDRESS: usize = 0x4F00;
+const DRIVER: [u8; 3] = [0x4C, DRIVER_ADDRESS as u8, (DRIVER_ADDRESS >> 8) as u8];
+
+pub struct NSFMapper {
+    num_banks: usize,
+    banks: [usize; 8],
+    vrc6: Option<VRC6Audio>,
+    fds: Option<FDSAudio>,
+}
+
+impl NSFMapper {
+    pub fn new(prg_len: usize, banks: [u8; 8], vrc6: bool, fds: bool) -> Self {
+        let mut mapper = NSFMapper {
+            num_banks: (prg_len / NSF_BANK_SIZE).max(1),
+            banks: [0; 8],
+            vrc6: if vrc6 { Some(VRC6Audio::default()) } else { None },
+            fds: if fds { Some(FDSAudio::default()) } else { None },
+        };
+        for (slot, &bank) in banks.iter().enumerate() {
+            mapper.banks[slot] = bank as usize;
+        }
+        mapper
+    }
+}
+
+impl Mapper for NSFMapper {
+    // FDS rips would have $8000-$DFFF as ram, they get the wavetable but not that
+    fn map_prg(&self, address: usize) -> PrgAddr {
+        match address {
+            0x6000..=0x7FFF => PrgAddr::Ram(address - 0x6000),
+            _ => {
+                let slot = (address - 0x8000) / NSF_BANK_SIZE;
+                let bank = self.banks[slot] % self.num_banks;
+                PrgAddr::Rom(bank * NSF_BANK_SIZE + (address % NSF_BANK_SIZE))
+            }
+        }
+    }
+
+    fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            0x5FF8..=0x5FFF => self.banks[address - 0x5FF8] = value as usize,
+            0x4040..=0x408A => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write_register(address, value);
+                }
+            }
+            0x9000..=0xB002 => {
+                if let Some(vrc6) = &mut self.vrc6 {
+                    vrc6.write_register(address, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_register(&mut self, address: usize) -> Option<u8> {
+        match address {
+            DRIVER_ADDRESS..=0x4F02 => Some(DRIVER[address - DRIVER_ADDRESS]),
+            0x4040..=0x4092 => self.fds.as_ref().and_then(|fds| fds.read_register(address)),
+            _ => None,
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical
+    }
+
+    fn clock_cpu(&mut self) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.clock();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |vrc6| vrc6.output())
+            + self.fds.as_ref().map_or(0.0, |fds| fds.output())
+    }
+}
@@ -0,0 +1,263 @@
+// vrc6.rs - mappers 24 (VRC6a, Akumajou Densetsu) and 26 (VRC6b, Madara, Esper Dream 2)
+// the two only differ in having A0 and A1 swapped on the register pins
+use super::mapper::{Mapper, PrgAddr, CHR_BANK_1K, PRG_BANK_16K, PRG_BANK_8K};
+use crate::apu::vrc6::VRC6Audio;
+use crate::cartridge::Mirroring;
+
+// cpu cycles per scanline is 341 / 3, the prescaler counts down in thirds
+const PRESCALER_RELOAD: i16 = 341;
+
+pub struct VRC6 {
+    swap_address_lines: bool,
+    prg_len: usize,
+    prg_bank_16k: usize,
+    prg_bank_8k: usize,
+    chr_banks: [usize; 8],
+    banking_mode: u8,
+    chr_a10_from_ppu: bool,
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_prescaler: i16,
+    irq_enabled: bool,
+    irq_enabled_after_ack: bool,
+    irq_cycle_mode: bool,
+    irq: bool,
+
+    audio: VRC6Audio,
+}
+
+impl VRC6 {
+    fn new(prg_len: usize, swap_address_lines: bool) -> Self {
+        VRC6 {
+            swap_address_lines: swap_address_lines,
+            prg_len: prg_len,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            banking_mode: 0,
+            chr_a10_from_ppu: false,
+            mirroring: Mirroring::Vertical,
+            prg_ram_enabled: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_prescaler: PRESCALER_RELOAD,
+            irq_enabled: false,
+            irq_enabled_after_ack: false,
+            irq_cycle_mode: false,
+            irq: false,
+            audio: VRC6Audio::default(),
+        }
+    }
+
+    pub fn new_vrc6a(prg_len: usize) -> Self {
+        VRC6::new(prg_len, false)
+    }
+
+    pub fn new_vrc6b(prg_len: usize) -> Self {
+        VRC6::new(prg_len, true)
+    }
+
+    // everything in vrc6a terms, $x000-$x003
+    fn decode(&self, address: usize) -> usize {
+        let reg = if self.swap_address_lines {
+            ((address & 0x01) << 1) | ((address & 0x02) >> 1)
+        } else {
+            address & 0x03
+        };
+        (address & 0xF000) | reg
+    }
+
+    fn write_ppu_banking(&mut self, value: u8) {
+        self.banking_mode = value & 0x03;
+        self.chr_a10_from_ppu = (value & 0x20) != 0;
+        self.prg_ram_enabled = (value & 0x80) != 0;
+
+        // bit 4 would take nametables from chr rom, nothing commercial relies on it
+        self.mirroring = match (value >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleLower,
+            _ => Mirroring::SingleUpper,
+        };
+    }
+
+    // a 2k bank out of a 1k register, bit 5 decides where the bottom bit comes from
+    fn chr_2k(&self, reg: usize, address: usize) -> usize {
+        let a10 = if self.chr_a10_from_ppu {
+            (address >> 10) & 1
+        } else {
+            self.chr_banks[reg] & 1
+        };
+        ((self.chr_banks[reg] & !1) | a10) * CHR_BANK_1K + (address % CHR_BANK_1K)
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for VRC6 {
+    fn map_prg(&self, address: usize) -> PrgAddr {
+        if self.prg_len == 0 {
+            return PrgAddr::OpenBus;
+        }
+
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => PrgAddr::Ram(address - 0x6000),
+            0x6000..=0x7FFF => PrgAddr::OpenBus,
+            0x8000..=0xBFFF => {
+                let bank = self.prg_bank_16k % (self.prg_len / PRG_BANK_16K);
+                PrgAddr::Rom(bank * PRG_BANK_16K + (address % PRG_BANK_16K))
+            }
+            0xC000..=0xDFFF => {
+                let bank = self.prg_bank_8k % (self.prg_len / PRG_BANK_8K);
+                PrgAddr::Rom(bank * PRG_BANK_8K + (address % PRG_BANK_8K))
+            }
+            _ => PrgAddr::Rom(self.prg_len - PRG_BANK_8K + (address % PRG_BANK_8K)),
+        }
+    }
+
+    fn map_chr(&self, address: usize) -> usize {
+        let slot = address / CHR_BANK_1K;
+        match self.banking_mode {
+            0 => self.chr_banks[slot] * CHR_BANK_1K + (address % CHR_BANK_1K),
+            1 => self.chr_2k(slot / 2, address),
+            _ => {
+                // 1k banks for the bottom half, 2k for the top
+                if slot < 4 {
+                    self.chr_banks[slot] * CHR_BANK_1K + (address % CHR_BANK_1K)
+                } else {
+                    self.chr_2k(4 + (slot - 4) / 2, address)
+                }
+            }
+        }
+    }
+
+    fn write_register(&mut self, address: usize, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        let address = self.decode(address);
+        match address {
+            0x8000..=0x8003 => self.prg_bank_16k = (value & 0x0F) as usize,
+            0x9000..=0xB002 => self.audio.write_register(address, value),
+            0xB003 => self.write_ppu_banking(value),
+            0xC000..=0xC003 => self.prg_bank_8k = (value & 0x1F) as usize,
+            0xD000..=0xD003 => self.chr_banks[address & 0x03] = value as usize,
+            0xE000..=0xE003 => self.chr_banks[4 + (address & 0x03)] = value as usize,
+            0xF000 => self.irq_latch = value,
+            0xF001 => {
+                self.irq_enabled_after_ack = (value & 0x01) != 0;
+                self.irq_enabled = (value & 0x02) != 0;
+                self.irq_cycle_mode = (value & 0x04) != 0;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_latch;
+                    self.irq_prescaler = PRESCALER_RELOAD;
+                }
+                self.irq = false;
+            }
+            0xF002 => {
+                self.irq = false;
+                self.irq_enabled = self.irq_enabled_after_ack;
+            }
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    // the irq counter runs off the cpu clock, either every cycle or once per ~scanline
+    fn clock_cpu(&mut self) {
+        self.audio.clock();
+
+        if !self.irq_enabled {
+            return;
+        }
+
+        if self.irq_cycle_mode {
+            self.clock_irq_counter();
+        } else {
+            self.irq_prescaler -= 3;
+            if self.irq_prescaler <= 0 {
+                self.irq_prescaler += PRESCALER_RELOAD;
+                self.clock_irq_counter();
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vrc6_tests() {
+        // 256k prg
+        let mut vrc6 = VRC6::new_vrc6a(256 * 1024);
+        vrc6.write_register(0x8000, 3);
+        vrc6.write_register(0xC000, 5);
+        assert_eq!(vrc6.map_prg(0x8000), PrgAddr::Rom(3 * PRG_BANK_16K));
+        assert_eq!(vrc6.map_prg(0xBFFF), PrgAddr::Rom(4 * PRG_BANK_16K - 1));
+        assert_eq!(vrc6.map_prg(0xC000), PrgAddr::Rom(5 * PRG_BANK_8K));
+        assert_eq!(vrc6.map_prg(0xE000), PrgAddr::Rom(31 * PRG_BANK_8K));
+
+        assert_eq!(vrc6.map_prg(0x6000), PrgAddr::OpenBus);
+        vrc6.write_register(0xB003, 0x84);
+        assert_eq!(vrc6.map_prg(0x6000), PrgAddr::Ram(0));
+        assert_eq!(vrc6.mirroring(), Mirroring::Horizontal);
+
+        vrc6.write_register(0xD002, 7);
+        assert_eq!(vrc6.map_chr(0x0810), 7 * CHR_BANK_1K + 0x10);
+
+        // vrc6b has A0/A1 swapped, so $E001 is the third register
+        let mut vrc6b = VRC6::new_vrc6b(256 * 1024);
+        vrc6b.write_register(0xE001, 9);
+        assert_eq!(vrc6b.map_chr(0x1800), 9 * CHR_BANK_1K);
+
+        // cycle mode irq, counts up from the latch and fires on overflow
+        vrc6.write_register(0xF000, 0xFD);
+        vrc6.write_register(0xF001, 0x07);
+        vrc6.clock_cpu();
+        vrc6.clock_cpu();
+        assert!(!vrc6.irq());
+        vrc6.clock_cpu();
+        assert!(vrc6.irq());
+
+        // acknowledging clears it, enable comes back from the E_A bit
+        vrc6.write_register(0xF002, 0);
+        assert!(!vrc6.irq());
+        vrc6.clock_cpu();
+        vrc6.clock_cpu();
+        vrc6.clock_cpu();
+        assert!(vrc6.irq());
+
+        // scanline mode takes 341/3 cpu cycles a count
+        vrc6.write_register(0xF000, 0xFF);
+        vrc6.write_register(0xF001, 0x02);
+        for _ in 0..113 {
+            vrc6.clock_cpu();
+        }
+        assert!(!vrc6.irq());
+        vrc6.clock_cpu();
+        assert!(vrc6.irq());
+    }
+}
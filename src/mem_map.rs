@@ -33,8 +33,27 @@ pub const PPU_REGISTERS_MIRRORS_END: usize = 0x3FFF;
 pub const APU_REGISTERS_START: usize = 0x4000;
 pub const APU_REGISTERS_END: usize = 0x4015;
 
+pub const APU_STATUS: usize = 0x4015;
+
+// writes to $4016 strobe both pads, writes to $4017 go to the apu frame counter
 pub const JOYPAD_ONE: usize = 0x4016;
 pub const JOYPAD_TWO: usize = 0x4017;
+pub const APU_FRAME_COUNTER: usize = 0x4017;
+
+// apu test registers, normally disabled
+pub const IO_TEST_START: usize = 0x4018;
+pub const IO_REGISTERS_END: usize = 0x401F;
+
+// cartridge expansion area, only some boards put anything here
+pub const EXPANSION_START: usize = 0x4020;
+pub const EXPANSION_END: usize = 0x5FFF;
+
+// cartridge work ram, battery backed on some boards
+pub const PRG_RAM_START: usize = 0x6000;
+pub const PRG_RAM_END: usize = 0x7FFF;
+
+// where a 512 byte trainer gets loaded
+pub const TRAINER_START: usize = 0x7000;
 
 pub const MIRROR_ONE_ROM_START: usize = 0x8000;
 pub const MIRROR_ONE_ROM_END: usize = 0xBFFF;
@@ -1,49 +1,106 @@
 // memory access - uses values in mem_map to check what address being passed actually is before
 // returning value
+use crate::apu::apu::APU;
+use crate::cartridge::{Cartridge, Header, Mirroring, Region, CHR_BLOCK_SIZE, PRG_BLOCK_SIZE, TRAINER_SIZE};
+use crate::cdl::{CodeDataLog, CHR_READ, CHR_RENDERED, PRG_CODE, PRG_DATA, PRG_INDIRECT_CODE, PRG_INDIRECT_DATA, PRG_PCM};
+use crate::cheat::game_genie::GameGenie;
+use crate::cheat::search::{Freezes, Width};
+use crate::controller::Controller;
+use crate::disk::DiskImage;
+use crate::mapper::fds::FDS;
+use crate::mapper::mapper::{create_mapper, Mapper, PrgAddr};
 use crate::mem_map::*;
+use crate::watch::{Access, Bus, Watchpoints};
 
 const RAM_SIZE: usize = 2 * 1024;
-const VRAM_SIZE: usize = 2 * 1024;
-const ROM_BLOCK_SIZE: usize = 16 * 1024;
-const CHR_BLOCK_SIZE: usize = 8 * 1024;
+// only four screen boards use the top 2k
+const VRAM_SIZE: usize = 4 * 1024;
+
+// cpu cycles the bus is tied up for
+const OAM_DMA_CYCLES: u16 = 513;
+const DMC_DMA_CYCLES: u16 = 4;
 
 pub struct RAM {
     ram: [u8; RAM_SIZE],
     rom: Box<[u8]>,
     ppu_ram: [u8; VRAM_SIZE],
     chr_ram: Box<[u8]>,
+    prg_ram: Box<[u8]>,
     ppu_regs: [u8; 8],
     ppu_reg_write: [u8; 8],
     ppu_reg_read: [u8; 8],
     OAM: [u8; 256],
-    universal_bg_color: u8,
+    oam_addr : u8,
     pallette_colors: [u8; 32],
-    num_prg_blocks : usize,
-    num_chr_blocks : usize,
-    mapper : u8,
-    mirror: u8,
+    mapper : Box<dyn Mapper>,
+    has_battery : bool,
+    region : Region,
+    prg_ram_dirty : bool,
+    chr_is_ram : bool,
+    apu : APU,
+    controllers : [Controller; 2],
+    oam_dma_pending : bool,
+    nmi_pending : bool,
+    dmc_stall_cycles : u16,
+    watchpoints : Watchpoints,
+    game_genie : GameGenie,
+    freezes : Freezes,
+    cdl : Option<CodeDataLog>,
+    // the bytes of the instruction being run, operand fetches are code not data
+    cdl_operands : (u16, u16),
 }
 
 impl RAM {
-    pub fn new(num_prg_blocks : usize, num_chr_blocks : usize, mapper : u8, mirror : u8) -> RAM {
+    // errors when create_mapper doesn't know the board
+    pub fn new(header : &Header) -> Result<RAM, String> {
+        Ok(RAM::with_mapper(header, create_mapper(header)?))
+    }
+
+    fn with_mapper(header : &Header, mapper : Box<dyn Mapper>) -> RAM {
+        let mut apu = APU::default();
+        apu.set_region(header.region);
         RAM {
             ram: [0; RAM_SIZE],
-            rom: vec![0; ROM_BLOCK_SIZE * num_prg_blocks].into_boxed_slice(),
+            rom: vec![0; PRG_BLOCK_SIZE * header.num_prg_blocks].into_boxed_slice(),
             ppu_ram: [0; VRAM_SIZE],
-            chr_ram: vec![0; CHR_BLOCK_SIZE * num_chr_blocks].into_boxed_slice(),
+            chr_ram: vec![0; CHR_BLOCK_SIZE * header.num_chr_blocks + header.chr_ram_size].into_boxed_slice(),
+            prg_ram: vec![0; header.prg_ram_size].into_boxed_slice(),
             ppu_regs: [0; 8],
             ppu_reg_write: [0; 8],
             ppu_reg_read: [0; 8],
             OAM: [0; 256],
-            universal_bg_color: 0,
+            oam_addr : 0,
             pallette_colors: [0; 32],
-            num_prg_blocks : num_prg_blocks,
-            num_chr_blocks : num_chr_blocks,
             mapper : mapper,
-            mirror : mirror,
+            has_battery : header.has_battery,
+            region : header.region,
+            prg_ram_dirty : false,
+            chr_is_ram : header.chr_ram_size != 0,
+            apu : apu,
+            controllers : [Controller::default(), Controller::default()],
+            oam_dma_pending : false,
+            nmi_pending : false,
+            dmc_stall_cycles : 0,
+            watchpoints : Watchpoints::default(),
+            game_genie : GameGenie::default(),
+            freezes : Freezes::default(),
+            cdl : None,
+            cdl_operands : (0, 0),
         }
     }
 
+    // for things that aren't an iNES cartridge, where the caller builds the board itself
+    pub fn with_board(header : &Header, rom : &[u8], mapper : Box<dyn Mapper>) -> RAM {
+        let mut ram = RAM::with_mapper(header, mapper);
+        ram.rom = rom.to_vec().into_boxed_slice();
+        ram
+    }
+
+    // the disk system boots from its bios, the game comes off the disk into prg ram
+    pub fn new_fds(bios : &[u8], disk : DiskImage) -> RAM {
+        RAM::with_board(&Header::fds(), bios, Box::new(FDS::new(disk)))
+    }
+
     pub fn clear_read_write_regs(&mut self) {
         self.ppu_reg_write = [0, 0, 0, 0, 0, 0, 0, 0];
         self.ppu_reg_read = [0, 0, 0, 0, 0, 0, 0, 0];
@@ -75,40 +132,255 @@ impl RAM {
 
     }
 
-    pub fn load_rom(&mut self, rom_data: Box<[u8]>) {
+    pub fn from_cartridge(cart : &Cartridge) -> Result<RAM, String> {
+        let mut ram = RAM::new(&cart.header)?;
+        ram.load_cartridge(cart);
+        Ok(ram)
+    }
+
+    pub fn load_cartridge(&mut self, cart : &Cartridge) {
 
-        let prg_len = self.num_prg_blocks * ROM_BLOCK_SIZE;
-        for i in 0..prg_len {
-            self.rom[i] = rom_data[i + 16];
+        // the trainer wants to live at $7000
+        if let Some(trainer) = &cart.trainer {
+            let trainer_base = TRAINER_START - PRG_RAM_START;
+            if self.prg_ram.len() >= trainer_base + TRAINER_SIZE {
+                self.prg_ram[trainer_base..trainer_base + TRAINER_SIZE].copy_from_slice(trainer);
+            }
         }
 
-        let chr_len = self.num_chr_blocks * CHR_BLOCK_SIZE;
-        for i in 0..chr_len {
-            self.chr_ram[i] = rom_data[prg_len + i + 16];
+        // prg smaller than a whole 16k block (some UNIF dumps) gets repeated to fill it
+        if !cart.prg.is_empty() {
+            for (i, byte) in self.rom.iter_mut().enumerate() {
+                *byte = cart.prg[i % cart.prg.len()];
+            }
         }
-        // self.block();
-        // panic!();
+
+        let chr_len = cart.chr.len().min(self.chr_ram.len());
+        self.chr_ram[..chr_len].copy_from_slice(&cart.chr[..chr_len]);
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    pub fn get_internal_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn get_prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    // restores a save, a short file only fills the start of ram
+    pub fn load_prg_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+        self.prg_ram_dirty = false;
+    }
+
+    pub fn is_prg_ram_dirty(&self) -> bool {
+        self.prg_ram_dirty
+    }
+
+    pub fn clear_prg_ram_dirty(&mut self) {
+        self.prg_ram_dirty = false;
+    }
+
+    pub fn get_apu(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    pub fn get_controller(&mut self, port: usize) -> &mut Controller {
+        &mut self.controllers[port]
+    }
+
+    // None unless this is running off a disk
+    pub fn get_disk_system(&mut self) -> Option<&mut FDS> {
+        self.mapper.disk_system()
+    }
+
+    // one cpu cycle for everything on the bus that isn't the cpu or ppu,
+    // the dmc steals the bus whenever it needs a sample byte
+    pub fn run_cycle(&mut self) {
+        if let Some(addr) = self.apu.dmc_fetch_address() {
+            let value = self.check_address_read(addr as usize);
+            self.log_prg(addr, PRG_PCM);
+            self.apu.dmc_fill(value);
+            self.dmc_stall_cycles += DMC_DMA_CYCLES;
+        }
+        self.mapper.clock_cpu();
+        self.apu.run(self.mapper.audio_output());
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.apu.irq() || self.mapper.irq()
+    }
+
+    // the ppu pulls the nmi line at the start of vblank, the cpu takes it on its next instruction
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        let pending = self.nmi_pending;
+        self.nmi_pending = false;
+        pending
+    }
+
+    // cycles the cpu has to sit out for dma, oam dma takes one more when it starts on an odd cycle
+    pub fn take_dma_stall(&mut self, odd_cycle: bool) -> u16 {
+        let mut stall = self.dmc_stall_cycles;
+        self.dmc_stall_cycles = 0;
+
+        if self.oam_dma_pending {
+            self.oam_dma_pending = false;
+            stall += OAM_DMA_CYCLES;
+            if odd_cycle {
+                stall += 1;
+            }
+        }
+        stall
+    }
+
+    pub fn get_watchpoints(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    pub fn get_game_genie(&mut self) -> &mut GameGenie {
+        &mut self.game_genie
+    }
+
+    pub fn get_freezes(&mut self) -> &mut Freezes {
+        &mut self.freezes
+    }
+
+    // starts logging how prg and chr rom get used, chr ram has nothing worth logging
+    pub fn start_cdl(&mut self) {
+        let chr_size = if self.chr_is_ram { 0 } else { self.chr_ram.len() };
+        self.cdl = Some(CodeDataLog::new(self.rom.len(), chr_size));
+    }
+
+    pub fn get_cdl(&mut self) -> Option<&mut CodeDataLog> {
+        self.cdl.as_mut()
+    }
+
+    // the cpu says where each instruction starts and how long it is
+    pub fn log_instruction(&mut self, addr: u16, len: u16) {
+        if self.cdl.is_none() {
+            return;
+        }
+        for i in 0..len {
+            self.log_prg(addr.wrapping_add(i), PRG_CODE);
+        }
+        self.cdl_operands = (addr, addr.wrapping_add(len));
+    }
+
+    // where a JMP ($nnnn) lands
+    pub fn log_indirect_code(&mut self, addr: u16) {
+        self.log_prg(addr, PRG_INDIRECT_CODE);
+    }
+
+    fn log_prg(&mut self, addr: u16, flags: u8) {
+        if self.cdl.is_none() || (addr as usize) < PRG_RAM_START {
+            return;
+        }
+        if let PrgAddr::Rom(offset) = self.mapper.map_prg(addr as usize) {
+            if let Some(cdl) = self.cdl.as_mut() {
+                cdl.log_prg(offset, addr, flags);
+            }
+        }
+    }
+
+    fn log_chr(&mut self, address: usize, flags: u8) {
+        if address <= PATTERN_TABLE_ONE_END {
+            if let Some(cdl) = self.cdl.as_mut() {
+                cdl.log_chr(self.mapper.map_chr(address), flags);
+            }
+        }
+    }
+
+    // once a frame, put frozen values back however the game changed them
+    pub fn apply_freezes(&mut self) {
+        for i in 0..self.freezes.get_frozen().len() {
+            let freeze = self.freezes.get_frozen()[i];
+            let address = freeze.address as usize;
+            self.check_address_write(address, freeze.value as u8);
+            if freeze.width == Width::Word {
+                self.check_address_write(address + 1, (freeze.value >> 8) as u8);
+            }
+        }
+    }
+
+    // opcode fetches, the only reads that count as execution
+    pub fn fetch_opcode(&mut self, addr: u16) -> u8 {
+        let value = self.check_address_read(addr as usize);
+        self.watchpoints.check(Bus::Cpu, Access::Execute, addr, value);
+        value
     }
 
     pub fn read_mem_value(&mut self, addr: u16) -> u8 {
-        self.check_address_read(addr as usize)
+        let value = self.check_address_read(addr as usize);
+        self.watchpoints.check(Bus::Cpu, Access::Read, addr, value);
+        let (start, end) = self.cdl_operands;
+        if addr.wrapping_sub(start) >= end.wrapping_sub(start) {
+            self.log_prg(addr, PRG_DATA);
+        }
+        value
+    }
+
+    // the final read of a ($nn,X) or ($nn),Y, through a pointer the game built
+    pub fn read_mem_indirect(&mut self, addr: u16) -> u8 {
+        let value = self.read_mem_value(addr);
+        self.log_prg(addr, PRG_INDIRECT_DATA);
+        value
     }
 
     pub fn read_mem_address(&mut self, addr: u16) -> u16 {
-        let byte_one = self.check_address_read(addr as usize);
-        let byte_two = self.check_address_read((addr + 1) as usize);
+        let byte_one = self.read_mem_value(addr);
+        let byte_two = self.read_mem_value(addr + 1);
         ((byte_two as u16) << 8) | (byte_one as u16)
     }
 
     pub fn write_mem_value(&mut self, addr: u16, value: u8) {
+        self.watchpoints.check(Bus::Cpu, Access::Write, addr, value);
         self.check_address_write(addr as usize, value);
     }
 
     pub fn write_mem_address(&mut self, addr: u16, new_addr: u16) {
         let byte_one = (new_addr) as u8;
         let byte_two = (new_addr >> 8) as u8;
-        self.check_address_write(addr as usize, byte_one);
-        self.check_address_write((addr + 1) as usize, byte_two);
+        self.write_mem_value(addr, byte_one);
+        self.write_mem_value(addr + 1, byte_two);
+    }
+
+    // the ppu picking up register writes, not a cpu access so no watchpoints
+    pub fn get_ppu_reg(&self, idx: usize) -> u8 {
+        self.ppu_regs[idx]
+    }
+
+    // and putting back what the cpu should see when it next reads one
+    pub fn set_ppu_reg(&mut self, idx: usize, value: u8) {
+        self.ppu_regs[idx] = value;
+    }
+
+    pub fn get_oam(&self) -> &[u8] {
+        &self.OAM
+    }
+
+    pub fn write_oam(&mut self, idx: u8, value: u8) {
+        self.OAM[idx as usize] = value;
+    }
+
+    pub fn get_oam_addr(&self) -> u8 {
+        self.oam_addr
+    }
+
+    pub fn set_oam_addr(&mut self, addr: u8) {
+        self.oam_addr = addr;
     }
 
     pub fn push_address_on_stack(&mut self, stack_ptr: &mut u8, push_address: u16) {
@@ -158,37 +430,31 @@ impl RAM {
                 let lookup = address & 0x7FF;
                 self.ram[lookup] = value;
             }
-            MIRROR_ONE_ROM_START..=MIRROR_ONE_ROM_END => {
-                let base = address - 0x8000;
-                self.rom[base] = value;
-            }
-            MIRROR_TWO_ROM_START..=MIRROR_TWO_ROM_END => {
-                let base =  match self.mapper {
-                    0x0 => {
-                        address - 0xC000
-                    },
-                    _ => {
-                        address
-                    },
-                };
-
-                self.rom[base] = value;
-            }
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRRORS_END => {
                 let base = address - 0x2000;
                 let indx = base % 8;
                 self.ppu_reg_write[indx] = 1;
                 self.ppu_regs[indx] = value;
             }
-            OAM_DMA => {
-                // writing a byte to this causes a 256 byte page to be copied to the OAM mem
-                // TODO cycles on cpu?
-                let page_addr = ((value as u16) << 8) as usize;
-                for i in 0..256 {
-                    self.OAM[i] = self.ram[page_addr + i];
+            APU_REGISTERS_START..=IO_REGISTERS_END => {
+                self.check_io_write(address, value);
+            }
+            EXPANSION_START..=EXPANSION_END => {
+                self.mapper.write_register(address, value);
+            }
+            PRG_RAM_START..=MIRROR_TWO_ROM_END => {
+                // rom writes are how games talk to the mapper, ram can sit alongside registers
+                if let PrgAddr::Ram(offset) = self.mapper.map_prg(address) {
+                    if !self.prg_ram.is_empty() {
+                        let base = offset % self.prg_ram.len();
+                        if self.prg_ram[base] != value {
+                            self.prg_ram[base] = value;
+                            self.prg_ram_dirty = true;
+                        }
+                    }
                 }
+                self.mapper.write_register(address, value);
             }
-
             _ => {
                 panic!("{:#x}", address);
             }
@@ -201,34 +467,74 @@ impl RAM {
                 let lookup = address & 0x7FF;
                 self.ram[lookup]
             }
-            MIRROR_ONE_ROM_START..=MIRROR_ONE_ROM_END => {
-                let base = address - 0x8000;
-                self.rom[base]
-            }
-            MIRROR_TWO_ROM_START..=MIRROR_TWO_ROM_END => {
-                let base =  match self.mapper {
-                    0x0 => {
-                        address - 0xC000
-                    },
-                    _ => {
-                        address
-                    },
-                };
-
-                self.rom[base]
-            }
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRRORS_END => {
                 let base = address - 0x2000;
                 let indx = base % 8;
                 self.ppu_reg_read[indx] = 1;
                 self.ppu_regs[indx]
             }
+            APU_REGISTERS_START..=IO_REGISTERS_END => {
+                self.check_io_read(address)
+            }
+            EXPANSION_START..=EXPANSION_END => {
+                // open bus unless the board answers
+                self.mapper.read_register(address).unwrap_or((address >> 8) as u8)
+            }
+            PRG_RAM_START..=MIRROR_TWO_ROM_END => {
+                match self.mapper.map_prg(address) {
+                    PrgAddr::Rom(offset) => {
+                        let value = self.rom[offset % self.rom.len()];
+                        self.game_genie.patch(address as u16, value)
+                    }
+                    PrgAddr::Ram(offset) if !self.prg_ram.is_empty() => {
+                        self.prg_ram[offset % self.prg_ram.len()]
+                    }
+                    _ => (address >> 8) as u8,
+                }
+            }
             _ => {
                 panic!("{:#x}", address);
             }
         }
     }
 
+    // $4000-$401F, split between the apu, the pads and oam dma
+    fn check_io_write(&mut self, address: usize, value: u8) {
+        match address {
+            OAM_DMA => {
+                // writing a byte to this causes a 256 byte page to be copied to the OAM mem,
+                // starting wherever OAMADDR points and wrapping round
+                let page_addr = (value as usize) << 8;
+                for i in 0..256 {
+                    let idx = self.oam_addr.wrapping_add(i as u8) as usize;
+                    self.OAM[idx] = self.check_address_read(page_addr + i);
+                }
+                self.oam_dma_pending = true;
+            }
+            JOYPAD_ONE => {
+                self.controllers[0].write_strobe(value);
+                self.controllers[1].write_strobe(value);
+            }
+            APU_REGISTERS_START..=APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(address, value);
+            }
+            _ => {
+                // test registers, nothing listening
+            }
+        }
+    }
+
+    fn check_io_read(&mut self, address: usize) -> u8 {
+        // the pads only drive the low bits, the rest is left over from the address
+        let open_bus = (address >> 8) as u8;
+        match address {
+            APU_STATUS => self.apu.read_status(),
+            JOYPAD_ONE => (open_bus & 0xE0) | self.controllers[0].read(),
+            JOYPAD_TWO => (open_bus & 0xE0) | self.controllers[1].read(),
+            _ => open_bus,
+        }
+    }
+
     pub fn write_ppu_data_no_incr(&mut self, value: u8) {
         self.ppu_regs[PPUDATA - 0x2000] = value
     }
@@ -238,64 +544,55 @@ impl RAM {
     }
 
     pub fn write_vram_value(&mut self, address: usize, value: u8) {
+        self.watchpoints.check(Bus::Ppu, Access::Write, address as u16, value);
         self.check_vram_write(address, value)
     }
 
+    // the ppu fetching tiles to draw
     pub fn read_vram_value(&mut self, address: usize) -> u8 {
+        self.log_chr(address, CHR_RENDERED);
+        self.ppu_fetch(address)
+    }
+
+    // $2007 reads, the game looking at vram rather than the ppu drawing it
+    pub fn read_ppu_data(&mut self, address: usize) -> u8 {
+        self.log_chr(address, CHR_READ);
+        self.ppu_fetch(address)
+    }
+
+    fn ppu_fetch(&mut self, address: usize) -> u8 {
         let val = self.check_vram_address_read(address);
+        self.watchpoints.check(Bus::Ppu, Access::Read, address as u16, val);
+
+        // some boards watch what the ppu fetches, mmc2 latches flip on tiles $FD and $FE
+        if address <= PATTERN_TABLE_ONE_END {
+            self.mapper.ppu_read(address);
+        }
         val
     }
 
+    // the four logical nametables onto the 2k inside the console (4k for four screen boards)
+    fn nametable_offset(&self, address: usize) -> usize {
+        let table = ((address - NAME_TABLE_ZERO_START) / 0x400) % 4;
+        let bank = match self.mapper.mirroring() {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleLower => 0,
+            Mirroring::SingleUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        bank * 0x400 + (address & 0x3FF)
+    }
+
     // maps vram addresses to other addresses
     fn check_vram_address_read(&self, address: usize) -> u8 {
-        //println!("read addr {:#x}", address);
         match address {
-            PATTERN_TABLE_ZERO_START..=PATTERN_TABLE_ZERO_END => self.chr_ram[address],
-            PATTERN_TABLE_ONE_START..=PATTERN_TABLE_ONE_END => self.chr_ram[address],
-            NAME_TABLE_ZERO_START..=NAME_TABLE_ZERO_END => self.ppu_ram[address - NAME_TABLE_ZERO_START],
-            NAME_TABLE_ONE_START..=NAME_TABLE_ONE_END => {  
-                                                            if self.mirror == 0 {
-                                                                self.ppu_ram[address - NAME_TABLE_ONE_START]
-                                                            } else {
-                                                                self.ppu_ram[address - NAME_TABLE_ZERO_START]
-                                                            }
-                                                         },
-            NAME_TABLE_TWO_START..=NAME_TABLE_TWO_END => {
-                                                                if self.mirror == 0 {
-                                                                    self.ppu_ram[address - NAME_TABLE_ONE_START]
-                                                                } else {
-                                                                    self.ppu_ram[address - NAME_TABLE_TWO_START]
-                                                                }
-                                                         },
-            NAME_TABLE_THREE_START..=NAME_TABLE_THREE_END => { 
-                                                                self.ppu_ram[address - NAME_TABLE_TWO_START]
-                                                             },
-            NAME_TABLE_ZERO_MIRROR_START..=NAME_TABLE_ZERO_MIRROR_END => self.ppu_ram[address - NAME_TABLE_ZERO_MIRROR_START],
-            NAME_TABLE_ONE_MIRROR_START..=NAME_TABLE_ONE_MIRROR_END => {
-                                                             if self.mirror == 0 {
-                                                                self.ppu_ram[address - NAME_TABLE_ONE_MIRROR_START]
-                                                             } else {
-                                                                self.ppu_ram[address - NAME_TABLE_ZERO_MIRROR_START]
-                                                             }
-                                                         },
-            NAME_TABLE_TWO_MIRROR_START..=NAME_TABLE_TWO_MIRROR_END => {
-                                                           if self.mirror == 0 {
-                                                              self.ppu_ram[address - NAME_TABLE_ONE_MIRROR_START]
-                                                           } else {
-                                                                self.ppu_ram[address - NAME_TABLE_TWO_MIRROR_START]
-                                                           }
-                                                          },
-            NAME_TABLE_THREE_MIRROR_START..=NAME_TABLE_THREE_MIRROR_END => { 
-                                                               self.ppu_ram[address - NAME_TABLE_TWO_MIRROR_START]
-                                                             }
-            PALLETE_RAM_INDICES_START..=PALLETE_RAM_INDICES_END => {
-                let base = address - PALLETE_RAM_INDICES_START;
-                self.pallette_colors[base]
-            },
-            PALLETE_RAM_MIRRORS_START..=PALLETE_RAM_MIRRORS_END => {
-                let base = (address - PALLETE_RAM_MIRRORS_START) % 0x20;
-                self.pallette_colors[base]
+            PATTERN_TABLE_ZERO_START..=PATTERN_TABLE_ONE_END => {
+                let base = self.mapper.map_chr(address);
+                self.chr_ram[base % self.chr_ram.len()]
             },
+            NAME_TABLE_ZERO_START..=NAME_TABLE_THREE_MIRROR_END => self.ppu_ram[self.nametable_offset(address)],
+            PALLETE_RAM_INDICES_START..=PALLETE_RAM_MIRRORS_END => self.pallette_colors[pallette_index(address)],
             _ => {
                 panic!("{:#x}", address);
             }
@@ -303,19 +600,21 @@ impl RAM {
     }
 
     fn check_vram_write(&mut self, address: usize, value: u8) {
-        println!("write addr {:#x} val {:#x}", address, value);
         match address {
-            NAME_TABLE_ZERO_START..=NAME_TABLE_ZERO_END => self.ppu_ram[address - NAME_TABLE_ZERO_START] = value,
-            NAME_TABLE_ONE_START..=NAME_TABLE_ONE_END => self.ppu_ram[address - NAME_TABLE_ZERO_START] = value,
-            NAME_TABLE_TWO_START..=NAME_TABLE_TWO_END => self.ppu_ram[address - NAME_TABLE_ZERO_START] = value,
-            NAME_TABLE_THREE_START..=NAME_TABLE_THREE_END => self.ppu_ram[address - NAME_TABLE_ZERO_START] = value,
-            PALLETE_RAM_INDICES_START..=PALLETE_RAM_INDICES_END => {
-                let base = address - PALLETE_RAM_INDICES_START;
-                self.pallette_colors[base] = value;
+            PATTERN_TABLE_ZERO_START..=PATTERN_TABLE_ONE_END => {
+                // only boards with chr ram can have their tiles written, rom just ignores it
+                if self.chr_is_ram {
+                    let base = self.mapper.map_chr(address) % self.chr_ram.len();
+                    self.chr_ram[base] = value;
+                }
             },
-            PALLETE_RAM_MIRRORS_START..=PALLETE_RAM_MIRRORS_END => {
-                let base = (address - PALLETE_RAM_MIRRORS_START) % 0x20;
-                self.pallette_colors[base] = value;
+            NAME_TABLE_ZERO_START..=NAME_TABLE_THREE_MIRROR_END => {
+                let base = self.nametable_offset(address);
+                self.ppu_ram[base] = value;
+            },
+            PALLETE_RAM_INDICES_START..=PALLETE_RAM_MIRRORS_END => {
+                // only 6 bits per entry, there are only 64 colors
+                self.pallette_colors[pallette_index(address)] = value & 0x3F;
             },
             _ => {
                 panic!("{:#x}", address);
@@ -323,6 +622,11 @@ impl RAM {
         }
     }
 
+    // the ppu looking up a color, 0 is the backdrop, 1-15 the background and 16-31 sprites
+    pub fn get_pallette_color(&self, idx: usize) -> u8 {
+        self.pallette_colors[pallette_index(PALLETE_RAM_INDICES_START + idx)]
+    }
+
     pub fn was_read(&self, idx: usize) -> bool {
         self.ppu_reg_read[idx] == 1
     }
@@ -332,6 +636,17 @@ impl RAM {
     }
 }
 
+// 32 entries repeated up to $3FFF, and the sprite palettes' color 0 at $3F10/$3F14/$3F18/$3F1C
+// is the same byte as the background's at $3F00/$3F04/$3F08/$3F0C
+fn pallette_index(address: usize) -> usize {
+    let idx = address & 0x1F;
+    if idx >= 0x10 && idx & 0x03 == 0 {
+        idx - 0x10
+    } else {
+        idx
+    }
+}
+
 pub fn swap_bytes(in_val: u16) -> u16 {
     let out_val = (in_val << 8) | (in_val >> 8);
     out_val
@@ -384,4 +699,186 @@ mod tests {
         // let stack_val = test_memory.pop_value_off_stack(&mut stack_ptr);
         // assert_eq!(stack_val, 0x86);
     }
+
+    #[test]
+    fn prg_ram_tests() {
+        use super::*;
+
+        let mut header = Header::default();
+        header.has_battery = true;
+        let mut test_memory: RAM = RAM::new(&header).unwrap();
+
+        test_memory.write_mem_value(0x6000, 0x42);
+        test_memory.write_mem_value(0x7FFF, 0x24);
+        assert_eq!(test_memory.read_mem_value(0x6000), 0x42);
+        assert_eq!(test_memory.read_mem_value(0x7FFF), 0x24);
+        assert!(test_memory.is_prg_ram_dirty());
+
+        test_memory.load_prg_ram(&[1, 2, 3]);
+        assert_eq!(test_memory.read_mem_value(0x6002), 3);
+        assert_eq!(test_memory.read_mem_value(0x7FFF), 0x24);
+        assert!(!test_memory.is_prg_ram_dirty());
+
+        // no ram on the board reads back open bus and ignores writes
+        header.prg_ram_size = 0;
+        let mut test_memory: RAM = RAM::new(&header).unwrap();
+        test_memory.write_mem_value(0x6000, 0x42);
+        assert_eq!(test_memory.read_mem_value(0x6000), 0x60);
+    }
+
+    #[test]
+    fn chr_ram_tests() {
+        use super::*;
+
+        let mut header = Header::default();
+        header.num_chr_blocks = 0;
+        header.chr_ram_size = 8 * 1024;
+        let mut test_memory: RAM = RAM::new(&header).unwrap();
+
+        test_memory.write_vram_value(0x0010, 0xAA);
+        test_memory.write_vram_value(0x1FFF, 0x55);
+        assert_eq!(test_memory.read_vram_value(0x0010), 0xAA);
+        assert_eq!(test_memory.read_vram_value(0x1FFF), 0x55);
+
+        // chr rom stays as it was loaded
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        test_memory.write_vram_value(0x0010, 0xAA);
+        assert_eq!(test_memory.read_vram_value(0x0010), 0);
+    }
+
+    #[test]
+    fn io_tests() {
+        use super::*;
+        use crate::controller::*;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+
+        // pads shift out one button per read after a strobe
+        test_memory.get_controller(0).set_button(BUTTON_B, true);
+        test_memory.write_mem_value(JOYPAD_ONE as u16, 1);
+        test_memory.write_mem_value(JOYPAD_ONE as u16, 0);
+        assert_eq!(test_memory.read_mem_value(JOYPAD_ONE as u16), 0x40);
+        assert_eq!(test_memory.read_mem_value(JOYPAD_ONE as u16), 0x41);
+        assert_eq!(test_memory.read_mem_value(JOYPAD_TWO as u16), 0x40);
+
+        // $4015 reads back which length counters are running
+        test_memory.write_mem_value(APU_STATUS as u16, 0x04);
+        test_memory.write_mem_value(0x400B, 0x08);
+        assert_eq!(test_memory.read_mem_value(APU_STATUS as u16) & 0x04, 0x04);
+
+        // oam dma can come from any page, and stalls the cpu
+        test_memory.write_mem_value(0x6010, 0x99);
+        test_memory.write_mem_value(OAM_DMA as u16, 0x60);
+        assert_eq!(test_memory.OAM[0x10], 0x99);
+        assert_eq!(test_memory.take_dma_stall(true), 514);
+        assert_eq!(test_memory.take_dma_stall(false), 0);
+
+        // with OAMADDR moved on the copy starts there and wraps
+        test_memory.set_oam_addr(0xF8);
+        test_memory.write_mem_value(0x6000, 0x11);
+        test_memory.write_mem_value(0x6008, 0x22);
+        test_memory.write_mem_value(OAM_DMA as u16, 0x60);
+        assert_eq!(test_memory.OAM[0xF8], 0x11);
+        assert_eq!(test_memory.OAM[0x00], 0x22);
+        assert_eq!(test_memory.OAM[0x08], 0x99);
+    }
+
+    #[test]
+    fn mirroring_tests() {
+        use super::*;
+
+        // mmc2 starts out vertical, $F000 switches it
+        let mut header = Header::default();
+        header.mapper = 9;
+        header.num_prg_blocks = 8;
+        let mut test_memory: RAM = RAM::new(&header).unwrap();
+
+        test_memory.write_vram_value(0x2005, 0x11);
+        assert_eq!(test_memory.read_vram_value(0x2805), 0x11);
+        assert_eq!(test_memory.read_vram_value(0x2405), 0);
+
+        test_memory.write_mem_value(0xF000, 1);
+        assert_eq!(test_memory.read_vram_value(0x2405), 0x11);
+        test_memory.write_vram_value(0x2C05, 0x22);
+        assert_eq!(test_memory.read_vram_value(0x2805), 0x22);
+        assert_eq!(test_memory.read_vram_value(0x3805), 0x22);
+
+        // boards we don't have are an error, not NROM in disguise
+        header.mapper = 4;
+        assert_eq!(RAM::new(&header).err(), Some("mapper 4 isn't supported".to_string()));
+
+        // nor is an mmc2 too small to fill its fixed banks
+        header.mapper = 9;
+        header.num_prg_blocks = 1;
+        assert!(RAM::new(&header).is_err());
+    }
+
+    #[test]
+    fn game_genie_tests() {
+        use super::*;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        let before = test_memory.read_mem_value(0xC123);
+
+        test_memory.get_game_genie().add("SXIOPO").unwrap();
+        assert_eq!(test_memory.read_mem_value(0x91D9), 0xAD);
+        assert_eq!(test_memory.read_mem_value(0xC123), before);
+
+        test_memory.get_game_genie().toggle(0);
+        assert_eq!(test_memory.read_mem_value(0x91D9), 0);
+    }
+
+    #[test]
+    fn pallette_tests() {
+        use super::*;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        test_memory.write_vram_value(0x3F00, 0x0F);
+        test_memory.write_vram_value(0x3F01, 0x30);
+        test_memory.write_vram_value(0x3F14, 0x16);
+        test_memory.write_vram_value(0x3F11, 0xFF);
+
+        assert_eq!(test_memory.get_pallette_color(0x00), 0x0F);
+        // $3F10 and friends are the same bytes as $3F00 and friends
+        assert_eq!(test_memory.read_vram_value(0x3F10), 0x0F);
+        assert_eq!(test_memory.get_pallette_color(0x04), 0x16);
+        assert_eq!(test_memory.get_pallette_color(0x14), 0x16);
+        // but the other sprite colors are their own
+        assert_eq!(test_memory.get_pallette_color(0x01), 0x30);
+        assert_eq!(test_memory.get_pallette_color(0x11), 0x3F);
+        // and the whole lot repeats up to $3FFF
+        assert_eq!(test_memory.read_vram_value(0x3FE1), 0x30);
+    }
+
+    #[test]
+    fn cdl_tests() {
+        use super::*;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        test_memory.start_cdl();
+
+        // LDA $9000 at $8000, its operand bytes are code even though they're read like data
+        test_memory.log_instruction(0x8000, 3);
+        test_memory.read_mem_address(0x8001);
+        test_memory.read_mem_value(0x9000);
+        test_memory.read_mem_indirect(0xC010);
+        test_memory.log_indirect_code(0xE000);
+        // ram isn't rom, nothing to log
+        test_memory.read_mem_value(0x0010);
+
+        test_memory.read_vram_value(0x0010);
+        test_memory.read_ppu_data(0x1000);
+        test_memory.read_vram_value(0x2000);
+
+        let cdl = test_memory.get_cdl().unwrap();
+        let prg = cdl.get_prg();
+        assert_eq!(prg.len(), 2 * PRG_BLOCK_SIZE);
+        assert_eq!(&prg[0..3], &[PRG_CODE; 3]);
+        assert_eq!(prg[0x1000], PRG_DATA);
+        assert_eq!(prg[0x4010], (2 << 2) | PRG_DATA | PRG_INDIRECT_DATA);
+        assert_eq!(prg[0x6000], (3 << 2) | PRG_INDIRECT_CODE);
+        assert_eq!(cdl.get_chr()[0x0010], CHR_RENDERED);
+        assert_eq!(cdl.get_chr()[0x1000], CHR_READ);
+        assert_eq!(cdl.get_chr().iter().filter(|&&f| f != 0).count(), 2);
+    }
 }
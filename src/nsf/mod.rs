@@ -0,0 +1,2 @@
+pub mod nsf;
+pub mod player;
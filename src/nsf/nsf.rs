@@ -0,0 +1,317 @@
+// nsf.rs - NSF and NSFe music rips, the sound code and data from a game plus where to call it
+// NSF is a fixed 128 byte header, NSFe is a chunked container that adds track names and lengths
+use crate::cartridge::Region;
+
+pub const NSF_HEADER_SIZE: usize = 0x80;
+
+// 60.1hz, what most NTSC rips want when they don't say otherwise
+const DEFAULT_PLAY_SPEED: u32 = 16639;
+// 50hz for PAL rips
+const DEFAULT_PAL_PLAY_SPEED: u32 = 19997;
+
+// region bits
+pub const REGION_PAL: u8 = 0x01;
+pub const REGION_DUAL: u8 = 0x02;
+
+// extra sound chip bits
+pub const EXPANSION_VRC6: u8 = 0x01;
+pub const EXPANSION_VRC7: u8 = 0x02;
+pub const EXPANSION_FDS: u8 = 0x04;
+pub const EXPANSION_MMC5: u8 = 0x08;
+pub const EXPANSION_N163: u8 = 0x10;
+pub const EXPANSION_S5B: u8 = 0x20;
+
+#[derive(Clone, Debug, Default)]
+pub struct NSF {
+    pub total_songs: u8,
+    // 0 based
+    pub start_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // microseconds between PLAY calls on NTSC and PAL
+    pub play_speed: u32,
+    pub pal_play_speed: u32,
+    pub region_flags: u8,
+    // None when the rip isn't bank switched
+    pub banks: Option<[u8; 8]>,
+    pub expansion: u8,
+    pub data: Vec<u8>,
+
+    // NSFe only, empty or None where the file doesn't say
+    pub track_labels: Vec<String>,
+    pub track_times: Vec<Option<u32>>,
+    pub track_fades: Vec<Option<u32>>,
+}
+
+pub fn is_nsf(data: &[u8]) -> bool {
+    data.starts_with(b"NESM\x1A") || data.starts_with(b"NSFE")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | ((data[offset + 1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data[offset] as u32
+        | ((data[offset + 1] as u32) << 8)
+        | ((data[offset + 2] as u32) << 16)
+        | ((data[offset + 3] as u32) << 24)
+}
+
+// fixed width fields are padded with zeros
+fn read_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+// NSFe packs lists of strings one after another, each zero terminated
+fn read_strings(data: &[u8]) -> Vec<String> {
+    let mut strings: Vec<String> = data.split(|&b| b == 0).map(read_string).collect();
+    if data.last() == Some(&0) {
+        strings.pop();
+    }
+    strings
+}
+
+fn parse_banks(data: &[u8]) -> Option<[u8; 8]> {
+    let mut banks = [0; 8];
+    let len = data.len().min(8);
+    banks[..len].copy_from_slice(&data[..len]);
+    if banks.iter().any(|&b| b != 0) {
+        Some(banks)
+    } else {
+        None
+    }
+}
+
+impl NSF {
+    pub fn parse(data: &[u8]) -> Result<NSF, String> {
+        if data.starts_with(b"NESM\x1A") {
+            NSF::parse_nsf(data)
+        } else if data.starts_with(b"NSFE") {
+            NSF::parse_nsfe(data)
+        } else {
+            Err("not an NSF or NSFe file".to_string())
+        }
+    }
+
+    fn parse_nsf(data: &[u8]) -> Result<NSF, String> {
+        if data.len() < NSF_HEADER_SIZE {
+            return Err(format!("{} bytes is too short for an NSF header", data.len()));
+        }
+
+        let speed = read_u16(data, 0x6E) as u32;
+        let pal_speed = read_u16(data, 0x78) as u32;
+        let total_songs = data[0x06];
+
+        Ok(NSF {
+            total_songs: total_songs,
+            start_song: data[0x07].saturating_sub(1),
+            load_address: read_u16(data, 0x08),
+            init_address: read_u16(data, 0x0A),
+            play_address: read_u16(data, 0x0C),
+            title: read_string(&data[0x0E..0x2E]),
+            artist: read_string(&data[0x2E..0x4E]),
+            copyright: read_string(&data[0x4E..0x6E]),
+            play_speed: if speed == 0 { DEFAULT_PLAY_SPEED } else { speed },
+            pal_play_speed: if pal_speed == 0 { DEFAULT_PAL_PLAY_SPEED } else { pal_speed },
+            region_flags: data[0x7A],
+            banks: parse_banks(&data[0x70..0x78]),
+            expansion: data[0x7B],
+            data: data[NSF_HEADER_SIZE..].to_vec(),
+            track_labels: Vec::new(),
+            track_times: vec![None; total_songs as usize],
+            track_fades: vec![None; total_songs as usize],
+        })
+    }
+
+    // chunks are a 4 byte length, a 4 byte id and then the data, up to NEND
+    fn parse_nsfe(data: &[u8]) -> Result<NSF, String> {
+        let mut nsf = NSF {
+            play_speed: DEFAULT_PLAY_SPEED,
+            pal_play_speed: DEFAULT_PAL_PLAY_SPEED,
+            ..NSF::default()
+        };
+        let mut has_info = false;
+        let mut pos = 4;
+
+        while pos + 8 <= data.len() {
+            let len = read_u32(data, pos) as usize;
+            let id = &data[pos + 4..pos + 8];
+            pos += 8;
+            if pos + len > data.len() {
+                return Err(format!("NSFe chunk {} runs off the end", read_string(id)));
+            }
+            let chunk = &data[pos..pos + len];
+            pos += len;
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err("NSFe INFO chunk is too short".to_string());
+                    }
+                    nsf.load_address = read_u16(chunk, 0);
+                    nsf.init_address = read_u16(chunk, 2);
+                    nsf.play_address = read_u16(chunk, 4);
+                    nsf.region_flags = chunk[6];
+                    nsf.expansion = chunk[7];
+                    nsf.total_songs = chunk.get(8).copied().unwrap_or(1);
+                    nsf.start_song = chunk.get(9).copied().unwrap_or(0);
+                    has_info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => nsf.banks = parse_banks(chunk),
+                b"RATE" if chunk.len() >= 2 => {
+                    let speed = read_u16(chunk, 0) as u32;
+                    if speed != 0 {
+                        nsf.play_speed = speed;
+                    }
+                    let pal_speed = if chunk.len() >= 4 { read_u16(chunk, 2) as u32 } else { 0 };
+                    if pal_speed != 0 {
+                        nsf.pal_play_speed = pal_speed;
+                    }
+                }
+                b"auth" => {
+                    let mut strings = read_strings(chunk).into_iter();
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => nsf.track_labels = read_strings(chunk),
+                b"time" => {
+                    nsf.track_times = chunk.chunks_exact(4).map(read_time).collect();
+                }
+                b"fade" => {
+                    nsf.track_fades = chunk.chunks_exact(4).map(read_time).collect();
+                }
+                b"NEND" => break,
+                // upper case chunks are ones we'd need to understand to play it right
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(format!("unknown required NSFe chunk {}", read_string(id)));
+                }
+                _ => {}
+            }
+        }
+
+        if !has_info || nsf.data.is_empty() {
+            return Err("NSFe is missing its INFO or DATA chunk".to_string());
+        }
+
+        nsf.track_times.resize(nsf.total_songs as usize, None);
+        nsf.track_fades.resize(nsf.total_songs as usize, None);
+        Ok(nsf)
+    }
+
+    // rips that play on either get played as NTSC
+    pub fn region(&self) -> Region {
+        if (self.region_flags & (REGION_PAL | REGION_DUAL)) == REGION_PAL {
+            Region::PAL
+        } else {
+            Region::NTSC
+        }
+    }
+
+    pub fn play_speed_for(&self, region: Region) -> u32 {
+        match region {
+            Region::NTSC => self.play_speed,
+            _ => self.pal_play_speed,
+        }
+    }
+
+    // track name if the NSFe has one, otherwise just the number
+    pub fn track_name(&self, track: u8) -> String {
+        match self.track_labels.get(track as usize) {
+            Some(label) if !label.is_empty() => label.clone(),
+            _ => format!("track {}", track + 1),
+        }
+    }
+}
+
+// milliseconds, negative means not set
+fn read_time(data: &[u8]) -> Option<u32> {
+    let time = read_u32(data, 0) as i32;
+    if time < 0 {
+        None
+    } else {
+        Some(time as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nsf_tests() {
+        let mut data = vec![0u8; NSF_HEADER_SIZE];
+        data[0..5].copy_from_slice(b"NESM\x1A");
+        data[0x06] = 3;
+        data[0x07] = 2;
+        data[0x08..0x0A].copy_from_slice(&[0x00, 0x80]);
+        data[0x0A..0x0C].copy_from_slice(&[0x03, 0x80]);
+        data[0x0C..0x0E].copy_from_slice(&[0x06, 0x80]);
+        data[0x0E..0x13].copy_from_slice(b"Tune\0");
+        data[0x7B] = EXPANSION_VRC6;
+        data.extend_from_slice(&[0x60; 16]);
+
+        let nsf = NSF::parse(&data).unwrap();
+        assert_eq!(nsf.total_songs, 3);
+        assert_eq!(nsf.start_song, 1);
+        assert_eq!(nsf.load_address, 0x8000);
+        assert_eq!(nsf.init_address, 0x8003);
+        assert_eq!(nsf.play_address, 0x8006);
+        assert_eq!(nsf.title, "Tune");
+        assert_eq!(nsf.play_speed, DEFAULT_PLAY_SPEED);
+        assert_eq!(nsf.banks, None);
+        assert_eq!(nsf.expansion, EXPANSION_VRC6);
+        assert_eq!(nsf.data.len(), 16);
+        assert_eq!(nsf.track_name(0), "track 1");
+        assert_eq!(nsf.region(), Region::NTSC);
+
+        // PAL rips carry their own rate, dual ones stay NTSC
+        data[0x78..0x7A].copy_from_slice(&[0x20, 0x4E]);
+        data[0x7A] = REGION_PAL;
+        let nsf = NSF::parse(&data).unwrap();
+        assert_eq!(nsf.region(), Region::PAL);
+        assert_eq!(nsf.play_speed_for(Region::PAL), 20_000);
+        assert_eq!(nsf.play_speed_for(Region::NTSC), DEFAULT_PLAY_SPEED);
+        data[0x7A] = REGION_PAL | REGION_DUAL;
+        assert_eq!(NSF::parse(&data).unwrap().region(), Region::NTSC);
+
+        // the same thing as NSFe, with names and times
+        let mut nsfe = b"NSFE".to_vec();
+        let mut chunk = |id: &[u8], body: &[u8]| {
+            nsfe.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            nsfe.extend_from_slice(id);
+            nsfe.extend_from_slice(body);
+        };
+        chunk(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, REGION_PAL, 0, 2, 1]);
+        chunk(b"DATA", &[0x60; 16]);
+        chunk(b"RATE", &[0x00, 0x00, 0x20, 0x4E]);
+        chunk(b"auth", b"Tune\0Someone\0");
+        chunk(b"tlbl", b"Intro\0Boss\0");
+        chunk(b"time", &[0x10, 0x27, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        chunk(b"NEND", &[]);
+
+        let nsf = NSF::parse(&nsfe).unwrap();
+        assert_eq!(nsf.total_songs, 2);
+        assert_eq!(nsf.start_song, 1);
+        assert_eq!(nsf.init_address, 0x8003);
+        assert_eq!(nsf.artist, "Someone");
+        assert_eq!(nsf.track_name(1), "Boss");
+        assert_eq!(nsf.track_times, vec![Some(10_000), None]);
+        assert_eq!(nsf.track_fades, vec![None, None]);
+        assert_eq!(nsf.region(), Region::PAL);
+        assert_eq!(nsf.play_speed, DEFAULT_PLAY_SPEED);
+        assert_eq!(nsf.pal_play_speed, 20_000);
+
+        let mut bad = b"NSFE".to_vec();
+        bad.extend_from_slice(&[0, 0, 0, 0]);
+        bad.extend_from_slice(b"WHAT");
+        assert!(NSF::parse(&bad).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+// player.rs - plays an NSF on the cpu and apu alone, the ppu never gets involved
+// INIT is called once per track with the track number in A, then PLAY at the rip's rate
+use super::nsf::{NSF, EXPANSION_FDS, EXPANSION_VRC6};
+use crate::apu::apu::SAMPLE_RATE;
+use crate::cartridge::{Header, Region};
+use crate::cpu::nes_6502::Nes6502;
+use crate::mapper::nsf::{NSFMapper, DRIVER_ADDRESS, NSF_BANK_SIZE};
+use crate::memory::RAM;
+
+const PRG_START: usize = 0x8000;
+
+pub struct NSFPlayer {
+    nsf: NSF,
+    rom: Vec<u8>,
+    banks: [u8; 8],
+    cpu: Nes6502,
+    ram: RAM,
+    track: u8,
+    region: Region,
+    // counts up a million per cpu cycle, PLAY is due every play_speed * cpu clock
+    play_timer: u64,
+    play_due: bool,
+}
+
+// bank switched rips are laid out in 4k banks from (load address & $FFF),
+// the rest just sit at their load address in a flat 32k
+fn build_rom(nsf: &NSF) -> (Vec<u8>, [u8; 8]) {
+    match nsf.banks {
+        Some(banks) => {
+            let padding = nsf.load_address as usize & (NSF_BANK_SIZE - 1);
+            let mut rom = vec![0; padding];
+            rom.extend_from_slice(&nsf.data);
+            let len = rom.len().div_ceil(NSF_BANK_SIZE) * NSF_BANK_SIZE;
+            rom.resize(len, 0);
+            (rom, banks)
+        }
+        None => {
+            let mut rom = vec![0; 0x8000];
+            let start = (nsf.load_address as usize).saturating_sub(PRG_START);
+            let len = nsf.data.len().min(rom.len().saturating_sub(start));
+            rom[start..start + len].copy_from_slice(&nsf.data[..len]);
+            (rom, [0, 1, 2, 3, 4, 5, 6, 7])
+        }
+    }
+}
+
+fn build_ram(nsf: &NSF, rom: &[u8], banks: [u8; 8]) -> RAM {
+    let header = Header {
+        num_prg_blocks: 0,
+        num_chr_blocks: 0,
+        region: nsf.region(),
+        ..Header::default()
+    };
+    let mapper = NSFMapper::new(
+        rom.len(),
+        banks,
+        (nsf.expansion & EXPANSION_VRC6) != 0,
+        (nsf.expansion & EXPANSION_FDS) != 0,
+    );
+    RAM::with_board(&header, rom, Box::new(mapper))
+}
+
+impl NSFPlayer {
+    pub fn new(nsf: NSF) -> Self {
+        let (rom, banks) = build_rom(&nsf);
+        let ram = build_ram(&nsf, &rom, banks);
+        let track = nsf.start_song;
+        let region = nsf.region();
+        let mut player = NSFPlayer {
+            nsf: nsf,
+            rom: rom,
+            banks: banks,
+            cpu: Nes6502::new(),
+            ram: ram,
+            track: 0,
+            region: region,
+            play_timer: 0,
+            play_due: false,
+        };
+        player.start_track(track);
+        player
+    }
+
+    pub fn get_nsf(&self) -> &NSF {
+        &self.nsf
+    }
+
+    pub fn get_track(&self) -> u8 {
+        self.track
+    }
+
+    // a fresh machine for every track, same as a real player's reset
+    pub fn start_track(&mut self, track: u8) {
+        self.track = track % self.nsf.total_songs.max(1);
+        self.ram = build_ram(&self.nsf, &self.rom, self.banks);
+        self.cpu = Nes6502::new();
+        self.play_timer = 0;
+        self.play_due = false;
+
+        // silence, and keep the frame counter's irq out of the way
+        for address in 0x4000..=0x4013 {
+            self.ram.write_mem_value(address, 0);
+        }
+        self.ram.write_mem_value(0x4015, 0x0F);
+        self.ram.write_mem_value(0x4017, 0x40);
+
+        // X = 0 asks for NTSC, 1 for PAL
+        let init = self.nsf.init_address;
+        let x = if self.region == Region::NTSC { 0 } else { 1 };
+        self.cpu.call(init, self.track, x, DRIVER_ADDRESS as u16, &mut self.ram);
+    }
+
+    pub fn next_track(&mut self) {
+        let track = self.track.wrapping_add(1);
+        self.start_track(track);
+    }
+
+    pub fn prev_track(&mut self) {
+        let track = if self.track == 0 { self.nsf.total_songs.max(1) - 1 } else { self.track - 1 };
+        self.start_track(track);
+    }
+
+    // PLAY waits until INIT (or the last PLAY) has returned
+    pub fn run_cycle(&mut self) {
+        self.cpu.run(&mut self.ram);
+        self.ram.run_cycle();
+
+        self.play_timer += 1_000_000;
+        let period = self.nsf.play_speed_for(self.region) as u64 * self.region.cpu_clock() as u64;
+        if self.play_timer >= period {
+            self.play_timer -= period;
+            self.play_due = true;
+        }
+
+        if self.play_due && self.cpu.get_pc() == DRIVER_ADDRESS as u16 {
+            self.play_due = false;
+            let play = self.nsf.play_address;
+            self.cpu.call(play, 0, 0, DRIVER_ADDRESS as u16, &mut self.ram);
+        }
+    }
+
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.ram.get_apu().take_samples()
+    }
+
+    // NSFe times if there are any
+    pub fn track_length_ms(&self) -> Option<u32> {
+        self.nsf.track_times.get(self.track as usize).copied().flatten()
+    }
+
+    pub fn track_fade_ms(&self) -> Option<u32> {
+        self.nsf.track_fades.get(self.track as usize).copied().flatten()
+    }
+
+    // plays length_ms of the current track then fades out over fade_ms, for writing out headless
+    pub fn render(&mut self, length_ms: u32, fade_ms: u32) -> Vec<f32> {
+        let total_ms = length_ms as u64 + fade_ms as u64;
+        let cycles = total_ms * self.region.cpu_clock() as u64 / 1000;
+        let mut samples = Vec::with_capacity((total_ms * SAMPLE_RATE as u64 / 1000) as usize);
+        for _ in 0..cycles {
+            self.run_cycle();
+        }
+        samples.extend(self.take_samples());
+
+        let fade_start = length_ms as u64 * SAMPLE_RATE as u64 / 1000;
+        let fade_len = (samples.len() as u64).saturating_sub(fade_start);
+        for (i, sample) in samples.iter_mut().skip(fade_start as usize).enumerate() {
+            *sample *= 1.0 - i as f32 / fade_len as f32;
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::apu::CPU_CLOCK;
+    use crate::nsf::nsf::{NSF_HEADER_SIZE, REGION_PAL};
+
+    #[test]
+    fn nsf_player_tests() {
+        let mut data = vec![0u8; NSF_HEADER_SIZE];
+        data[0..5].copy_from_slice(b"NESM\x1A");
+        data[0x06] = 2;
+        data[0x07] = 1;
+        data[0x08..0x0A].copy_from_slice(&[0x00, 0x80]);
+        data[0x0A..0x0C].copy_from_slice(&[0x00, 0x80]);
+        data[0x0C..0x0E].copy_from_slice(&[0x03, 0x80]);
+        // INIT: sta $00, rts   PLAY: inc $01, rts
+        data.extend_from_slice(&[0x85, 0x00, 0x60, 0xE6, 0x01, 0x60]);
+
+        let mut player = NSFPlayer::new(NSF::parse(&data).unwrap());
+        player.next_track();
+        assert_eq!(player.get_track(), 1);
+
+        // one second is ~60 PLAY calls
+        for _ in 0..CPU_CLOCK {
+            player.run_cycle();
+        }
+        let ram = player.ram.get_internal_ram();
+        assert_eq!(ram[0], 1);
+        assert!(ram[1] >= 59 && ram[1] <= 61, "{} PLAY calls", ram[1]);
+
+        player.next_track();
+        assert_eq!(player.get_track(), 0);
+        player.prev_track();
+        assert_eq!(player.get_track(), 1);
+
+        let samples = player.render(100, 100);
+        assert!((samples.len() as i64 - (SAMPLE_RATE / 5) as i64).abs() <= 1);
+
+        // a PAL rip gets X = 1 and ~50 PLAY calls a second on the PAL clock
+        data[0x7A] = REGION_PAL;
+        data[NSF_HEADER_SIZE..NSF_HEADER_SIZE + 2].copy_from_slice(&[0x86, 0x00]);
+        let mut player = NSFPlayer::new(NSF::parse(&data).unwrap());
+        for _ in 0..Region::PAL.cpu_clock() {
+            player.run_cycle();
+        }
+        let ram = player.ram.get_internal_ram();
+        assert_eq!(ram[0], 1);
+        assert!(ram[1] >= 49 && ram[1] <= 51, "{} PLAY calls", ram[1]);
+    }
+}
@@ -1,4 +1,7 @@
 //pallette.rs - the color pallette defined in rgb for NES
+use std::fs;
+use std::io;
+use std::path::Path;
 
 // 64 colors
 
@@ -74,4 +77,128 @@ pub const color_pallette : [(u8,u8,u8); 64] = [
     (0xa7, 0xa7, 0xa7),
     (0x00, 0x00, 0x00),
     (0x00, 0x00, 0x00),    
-];
\ No newline at end of file
+];
+
+// PPUMASK's top three bits darken the other two channels, one set of 64 for each combination
+const EMPHASIS_SETS: usize = 8;
+// .pal files are plain rgb triples, either the 64 colors or all 8 emphasis sets of them
+pub const PAL_FILE_SIZE: usize = 64 * 3;
+pub const PAL_FILE_EMPHASIS_SIZE: usize = EMPHASIS_SETS * PAL_FILE_SIZE;
+const EMPHASIS_RED: u8 = 0x01;
+const EMPHASIS_GREEN: u8 = 0x02;
+const EMPHASIS_BLUE: u8 = 0x04;
+
+// the colors actually drawn, indexed by emphasis bits then palette entry
+pub struct Pallette {
+    colors : Vec<(u8,u8,u8)>,
+}
+
+impl Pallette {
+    pub fn from_base(base: &[(u8,u8,u8)]) -> Pallette {
+        let mut colors = Vec::with_capacity(EMPHASIS_SETS * 64);
+        for emphasis in 0..EMPHASIS_SETS as u8 {
+            for (idx, &color) in base.iter().enumerate() {
+                colors.push(emphasize(idx as u8, color, emphasis));
+            }
+        }
+        Pallette {
+            colors: colors,
+        }
+    }
+
+    pub fn get_color(&self, color: u8, emphasis: u8) -> (u8,u8,u8) {
+        self.colors[(emphasis as usize & 0x07) * 64 + (color as usize & 0x3F)]
+    }
+
+    // a 64 color file gets its emphasis sets worked out, a full one is used as is
+    pub fn from_pal_bytes(data: &[u8]) -> Result<Pallette, String> {
+        let colors: Vec<(u8,u8,u8)> = data.chunks_exact(3).map(|rgb| (rgb[0], rgb[1], rgb[2])).collect();
+        match data.len() {
+            PAL_FILE_SIZE => Ok(Pallette::from_base(&colors)),
+            PAL_FILE_EMPHASIS_SIZE => Ok(Pallette {
+                colors: colors,
+            }),
+            len => Err(format!("a .pal file is {} or {} bytes, not {}", PAL_FILE_SIZE, PAL_FILE_EMPHASIS_SIZE, len)),
+        }
+    }
+
+    pub fn to_pal_bytes(&self, with_emphasis: bool) -> Vec<u8> {
+        let count = if with_emphasis { EMPHASIS_SETS * 64 } else { 64 };
+        self.colors[..count].iter().flat_map(|&(r, g, b)| vec![r, g, b]).collect()
+    }
+}
+
+pub fn load_pal_file<P: AsRef<Path>>(path: P) -> io::Result<Pallette> {
+    let data = fs::read(path)?;
+    Pallette::from_pal_bytes(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_pal_file<P: AsRef<Path>>(path: P, pallette: &Pallette, with_emphasis: bool) -> io::Result<()> {
+    fs::write(path, pallette.to_pal_bytes(with_emphasis))
+}
+
+impl Default for Pallette {
+    fn default() -> Self {
+        Pallette::from_base(&color_pallette)
+    }
+}
+
+// roughly what the hardware does, each emphasized channel leaves the rest at about 80%
+// the blacks in columns $E and $F aren't affected
+fn emphasize(idx: u8, color: (u8,u8,u8), emphasis: u8) -> (u8,u8,u8) {
+    if emphasis == 0 || idx & 0x0F >= 0x0E {
+        return color;
+    }
+    let dim = |channel: u8, keep: u8| {
+        if emphasis & !keep != 0 {
+            (channel as u16 * 13 / 16) as u8
+        } else {
+            channel
+        }
+    };
+    let (r, g, b) = color;
+    (dim(r, EMPHASIS_RED), dim(g, EMPHASIS_GREEN), dim(b, EMPHASIS_BLUE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emphasis_tests() {
+        let pallette = Pallette::default();
+        assert_eq!(pallette.get_color(0x20, 0), color_pallette[0x20]);
+        // red emphasis leaves red alone and dims green and blue
+        assert_eq!(pallette.get_color(0x20, EMPHASIS_RED), (0xff, 0xcf, 0xcf));
+        // every channel is emphasized by something else
+        assert_eq!(pallette.get_color(0x20, 0x07), (0xcf, 0xcf, 0xcf));
+        assert_eq!(pallette.get_color(0x2D, 0x07), (0x22, 0x22, 0x22));
+        assert_eq!(pallette.get_color(0x3E, 0x07), color_pallette[0x3E]);
+    }
+
+    #[test]
+    fn pal_file_tests() {
+        let pallette = Pallette::default();
+        let small = pallette.to_pal_bytes(false);
+        let full = pallette.to_pal_bytes(true);
+        assert_eq!(small.len(), PAL_FILE_SIZE);
+        assert_eq!(full.len(), PAL_FILE_EMPHASIS_SIZE);
+        assert_eq!(&small[0x21 * 3..0x21 * 3 + 3], &[0x1f, 0x9e, 0xff][..]);
+
+        // 64 colors get emphasis made up for them, 512 are taken as they come
+        let mut data = small.clone();
+        data[0x21 * 3] = 0x80;
+        let loaded = Pallette::from_pal_bytes(&data).unwrap();
+        assert_eq!(loaded.get_color(0x21, 0), (0x80, 0x9e, 0xff));
+        assert_eq!(loaded.get_color(0x21, EMPHASIS_BLUE), (0x68, 0x80, 0xff));
+
+        let mut data = full.clone();
+        data[(64 + 0x21) * 3] = 0x80;
+        let loaded = Pallette::from_pal_bytes(&data).unwrap();
+        assert_eq!(loaded.get_color(0x21, 0), color_pallette[0x21]);
+        assert_eq!(loaded.get_color(0x21, EMPHASIS_RED).0, 0x80);
+        assert_eq!(loaded.to_pal_bytes(true), data);
+
+        assert!(Pallette::from_pal_bytes(&small[..191]).is_err());
+    }
+}
@@ -1,47 +1,110 @@
+use crate::cartridge::Region;
 use crate::mem_map::*;
 use crate::memory::RAM;
+use super::pallette::Pallette;
 
-use std::io::{stdin, stdout, Read, Write};
-
-const NAME_TABLE_ADDRS: [usize; 4] = [0x2000, 0x2400, 0x2800, 0x2C00];
 const VRAM_INCRS: [usize; 2] = [1, 32];
 const PAT_TABLE_ADDR: [usize; 2] = [0, 0x1000];
 
 const WIDTH: u32 = 256;
 const HEIGHT: u32 = 240;
 
+// sprites a line can show, the rest are dropped
+const MAX_LINE_SPRITES: usize = 8;
+
+// sprite attribute bits
+const SPRITE_PALLETTE: u8 = 0x03;
+const SPRITE_BEHIND_BG: u8 = 0x20;
+const SPRITE_FLIP_H: u8 = 0x40;
+const SPRITE_FLIP_V: u8 = 0x80;
+
+// 341 dots a line, 240 visible lines then idle and vblank lines, the region says how many,
+// and last the pre render line where the vertical scroll gets copied into v
+const DOTS_PER_LINE: u16 = 341;
+const POST_RENDER_LINE: u16 = 240;
+
+// PPUSTATUS bits, the low 5 are whatever was last written to any ppu register
+const STATUS_OVERFLOW: u8 = 0x20;
+const STATUS_SPRITE_HIT: u8 = 0x40;
+const STATUS_VBLANK: u8 = 0x80;
+
+// dots between vblank starting and nmi reaching the cpu, a $2002 read in between cancels it
+const NMI_DELAY: u8 = 2;
+
+// v and t are laid out yyy NN YYYYY XXXXX, fine y, nametable, coarse y, coarse x
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+const HORIZONTAL_BITS: u16 = COARSE_X | NAMETABLE_X;
+const VERTICAL_BITS: u16 = FINE_Y | NAMETABLE_Y | COARSE_Y;
+
 #[derive(Default)]
 pub struct PPU {
     PPUCTRL: ppuCtrl,
     PPUMASK: ppuMask,
     PPUSTATUS: ppuStatus, // read ONLY
-    OAMADDR: u8,
     OAMDATA: u8,
-    PPUSCROLL: ppuScroll,
-    PPUADDR: ppuAddr,
 
     // memory that will represent "composited" image, and will be used for output by system
     output : output_image,
+    pallette : Pallette,
     current_offset : u16,
     current_cycle  : u16,
     cycles_until_action : u8,
 
     current_scan_line : u16,
+    frame : u64,
+    region : Region,
 
+    // loopy's v, t, x and w, shared between $2005/$2006 writes and rendering
     current_vram_address : u16,
     temp_vram_address : u16,
     fine_x_scroll : u8,
     first_second_write_toggle : u8,
 
-    // shift register
+    // the next tile, fetched a tile ahead of where it gets drawn
+    next_tile_id : u8,
     tileOne : u8,
     tileTwo : u8,
     pal_attrib_one : u8,
 
-    current_x : u16,
+    // shift registers, the tile being drawn in the top byte and the next one below it
+    // the attribute ones hold the palette bits spread out to a byte per tile
+    bg_pattern_lo : u16,
+    bg_pattern_hi : u16,
+    bg_attrib_lo : u16,
+    bg_attrib_hi : u16,
+
+    // sprites found for the next line, 4 bytes each as they are in OAM
+    secondary_oam : [u8; MAX_LINE_SPRITES * 4],
+    sprites_found : usize,
+    // what gets drawn on this line, fetched at the end of the one before
+    sprites : [SpriteUnit; MAX_LINE_SPRITES],
+    sprite_count : usize,
+    // OAM sprite 0 is in the first slot, for sprite 0 hits
+    sprite_zero_found : bool,
+    sprite_zero_on_line : bool,
+
+    // last value written to any register, what the low bits of PPUSTATUS read back
+    io_latch : u8,
+    // $2007 reads hand back what the previous read fetched
+    read_buffer : u8,
+    // nmi output is vblank and PPUCTRL bit 7 together, the cpu sees it going high
+    nmi_line : bool,
+    nmi_delay : u8,
+    // PPUSTATUS read just as vblank starts, so this frame's flag and nmi never happen
+    suppress_vblank : bool,
+}
 
-    name_table_addr : usize,
-    v_blank : bool,
+// one sprite's row of pixels, ready to draw
+#[derive(Default, Clone, Copy)]
+struct SpriteUnit {
+    x : u8,
+    attrib : u8,
+    pattern_lo : u8,
+    pattern_hi : u8,
 }
 
 fn get_bit(byte: u8, index: u8) -> u8 {
@@ -55,15 +118,21 @@ impl PPU {
         // run?
         let mut reg = PPU_REGISTERS_START;
 
+        for idx in 0..8 {
+            if mem.was_written(idx) {
+                self.io_latch = mem.get_ppu_reg(idx);
+            }
+        }
+
         if mem.was_written(0) {
-            let ppuCtrlVal = mem.read_mem_value(reg as u16);
+            let ppuCtrlVal = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
             self.updatePpuCtrl(ppuCtrlVal);
         }
 
         reg += 1;
 
         if mem.was_written(1) {
-            let ppuMaskVal = mem.read_mem_value(reg as u16);
+            let ppuMaskVal = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
             self.updatePpuMask(ppuMaskVal);
         }
 
@@ -77,159 +146,526 @@ impl PPU {
         reg += 1;
 
         if mem.was_written(3) {
-            let OAMaddr = mem.read_mem_value(reg as u16);
-            self.OAMADDR = OAMaddr;
+            let OAMaddr = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
+            self.updateOAMAddr(mem, OAMaddr);
         }
 
         reg += 1;
 
         if mem.was_written(4) {
-            let OAMdata = mem.read_mem_value(reg as u16);
-            // TODO
+            let OAMdata = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
+            self.updateOAMData(mem, OAMdata);
         }
 
         reg += 1;
 
         if mem.was_written(5) {
-            let scroll = mem.read_mem_value(reg as u16);
+            let scroll = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
             self.updatePpuScroll(scroll);
         }
 
         reg += 1;
 
         if mem.was_written(6) {
-            let addr = mem.read_mem_value(reg as u16);
+            let addr = mem.get_ppu_reg(reg - PPU_REGISTERS_START);
             self.updatePpuAddr(addr);
         }
 
-        reg += 1;
-
         if mem.was_written(7) {
             let value = mem.read_ppu_data_no_incr();
-            // write value to mem address stored in ppu addr
-            let addr = self.PPUADDR.address as usize;
+            // write value to mem address stored in v, the ppu bus is only 14 bits
+            let addr = (self.current_vram_address & 0x3FFF) as usize;
             mem.write_vram_value(addr, value);
-            self.PPUADDR.address += self.PPUCTRL.VRAM_address_increment;
+            self.increment_vram_address();
         }
 
         if mem.was_read(7) {
-            let addr = self.PPUADDR.address as u16;
-            let value = mem.read_mem_value(addr);
-            mem.write_ppu_data_no_incr(value);
-            self.PPUADDR.address += self.PPUCTRL.VRAM_address_increment;
-            // TODO potential problem with internal read buffer?
+            // the cpu already has the old buffer, refill it from where v points
+            // palettes were returned straight away, the buffer gets the nametable under them
+            let addr = (self.current_vram_address & 0x3FFF) as usize;
+            self.read_buffer = if addr >= PALLETE_RAM_INDICES_START {
+                mem.read_ppu_data(addr & 0x2FFF)
+            } else {
+                mem.read_ppu_data(addr)
+            };
+            self.increment_vram_address();
         }
 
         mem.clear_read_write_regs();
             self.do_scan_work(mem);
+            self.update_nmi(mem);
+
+        // what the cpu sees if it reads PPUSTATUS or OAMDATA before the next dot
+        let status = self.status_byte();
+        mem.set_ppu_reg(PPUSTATUS - PPU_REGISTERS_START, status);
+        let data = self.ppu_data(mem);
+        mem.write_ppu_data_no_incr(data);
+        // $2004 reads whatever OAMADDR points at, without moving it on
+        let oam_value = self.read_oam_data(mem);
+        mem.set_ppu_reg(OAMDATA - PPU_REGISTERS_START, oam_value);
         }
 
+    // palette entries are only 6 bits, the top two are left over on the bus
+    fn ppu_data(&self, mem: &RAM) -> u8 {
+        let addr = (self.current_vram_address & 0x3FFF) as usize;
+        if addr >= PALLETE_RAM_INDICES_START {
+            (self.io_latch & 0xC0) | mem.get_pallette_color(addr - PALLETE_RAM_INDICES_START)
+        } else {
+            self.read_buffer
+        }
+    }
+
+    fn status_byte(&self) -> u8 {
+        let mut status = self.io_latch & 0x1F;
+        if self.PPUSTATUS.overflow != 0 {
+            status |= STATUS_OVERFLOW;
+        }
+        if self.PPUSTATUS.sprite_hit != 0 {
+            status |= STATUS_SPRITE_HIT;
+        }
+        if self.PPUSTATUS.vert_blank_started != 0 {
+            status |= STATUS_VBLANK;
+        }
+        status
+    }
+
+    // nmi fires on the line going high, which turning nmi on during vblank does too
+    fn update_nmi(&mut self, mem: &mut RAM) {
+        let line = self.PPUSTATUS.vert_blank_started != 0 && self.PPUCTRL.gen_nmi != 0;
+        if line && !self.nmi_line {
+            self.nmi_delay = NMI_DELAY;
+        } else if !line {
+            self.nmi_delay = 0;
+        } else if self.nmi_delay > 0 {
+            self.nmi_delay -= 1;
+            if self.nmi_delay == 0 {
+                mem.trigger_nmi();
+            }
+        }
+        self.nmi_line = line;
+    }
+
     fn do_scan_work(&mut self, mem: &mut RAM){
         // 3 ppu cycles per normal cpu cycle
-        //let mut attrib_table_addr = ;
-        //let 0 be -1 or pre render scanline
+        // lines 0-239 are drawn, 261 is the pre render line
 
         match self.current_scan_line {
-            0 => {
-                //self.v_blank = false;
-                self.name_table_addr = self.PPUCTRL.nametableAddress;
-                self.current_x = 0;
+            line if line < POST_RENDER_LINE || line == self.pre_render_line() => {
+                self.render_dot(mem);
+            },
+            POST_RENDER_LINE => {
+               // post scanline
             },
-            1..=241 => {
-                // different stages of "work"
-                match self.current_cycle {
+            line if line == self.vblank_line() && self.current_cycle == 1 => {
+                // vert blank starts
+                if !self.suppress_vblank {
+                    self.PPUSTATUS.vert_blank_started = 1;
+                }
+                self.suppress_vblank = false;
+            }
+            _=> {
+                // vert blank
+            }
+        }
+
+        // NTSC odd frames with rendering on skip the pre render line's last dot
+        let skip_dot = self.current_scan_line == self.pre_render_line() && self.current_cycle == DOTS_PER_LINE - 2
+            && self.frame % 2 == 1 && self.rendering_enabled() && self.region == Region::NTSC;
+
+        self.current_cycle = (self.current_cycle + 1) % DOTS_PER_LINE;
+        if self.current_cycle == 0 || skip_dot {
+            self.current_cycle = 0;
+            self.current_scan_line = (self.current_scan_line + 1) % self.region.scanlines();
+            if self.current_scan_line == 0 {
+                self.frame += 1;
+            }
+        }
+
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn set_pallette(&mut self, pallette: Pallette) {
+        self.pallette = pallette;
+    }
+
+    pub fn get_pallette(&self) -> &Pallette {
+        &self.pallette
+    }
+
+    fn vblank_line(&self) -> u16 {
+        self.region.vblank_line()
+    }
+
+    fn pre_render_line(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    pub fn get_scanline(&self) -> u16 {
+        self.current_scan_line
+    }
+
+    pub fn get_dot(&self) -> u16 {
+        self.current_cycle
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.PPUMASK.show_bg != 0 || self.PPUMASK.show_spr != 0
+    }
+
+    // one dot of a visible or pre render line
+    fn render_dot(&mut self, mem: &mut RAM) {
+        let dot = self.current_cycle;
+        let visible = self.current_scan_line != self.pre_render_line();
+
+        // the flags last until the end of vblank whether or not anyone read them
+        if !visible && dot == 1 {
+            self.PPUSTATUS.vert_blank_started = 0;
+            self.PPUSTATUS.sprite_hit = 0;
+            self.PPUSTATUS.overflow = 0;
+        }
+
+        if self.rendering_enabled() {
+            // 8 dots per tile, the first two tiles of the next line are fetched at the end of this one
+            if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+                self.shift_background();
+                match (dot - 1) % 8 {
                     0 => {
-                        self.current_x = 0;
+                        self.load_background_shifters();
+                        let name_table_addr = 0x2000 | (self.current_vram_address & 0x0FFF);
+                        self.next_tile_id = mem.read_vram_value(name_table_addr as usize);
+                    },
+                    2 => {
+                        let attrib = mem.read_vram_value(self.attribute_addr());
+                        self.pal_attrib_one = self.attribute_palette(attrib);
                     },
-                    1..=256 => {
-                        let pixel = ( self.current_cycle - 1 ) % 8;
-                        if pixel == 0 {
-                            // get the values needed!
-                            let index = mem.read_vram_value(self.name_table_addr) as u16;
-
-                            // debug - can we print the chr?
-                            // for every scan line there are 32 ( 8 * 32 = 256) tiles / 256 pixels
-                            // each tile has 16 bytes (8 lines of two byte each - one for each bitplane)
-                            // each scan line goes through 2 of these bytes per tile
-
-                            // each 8 pixel increment moves onto next pair of bytes 16 bytes on
-                            // each scan line is a byte apart from the one above
-
-                            // so offset is scan line * 64 bytes (32 bytes )  plus current tile num 
-
-                            let current_tile_x = self.current_x / 8;
-                            let current_tile_y = (self.current_scan_line - 1) / 8;
-                            let current_row = (self.current_scan_line - 1) % 8;
-
-                            let offset = index + current_row + self.PPUCTRL.bg_pattern_table_addr as u16; //current_tile_x * 16 + current_tile_y * 512 + current_row;
-
-                            //let offset = // + self.PPUCTRL.bg_pattern_table_addr as u16;
-                            self.tileOne = mem.read_vram_value(offset as usize);
-                            self.tileTwo = mem.read_vram_value(offset as usize + 8);
-                            self.name_table_addr += 1;
-                        }
-
-                        let pixVal = (((self.tileOne >> (7 - pixel)) & 1) * 2) +  ((self.tileTwo >> (7 - pixel)) & 1);
-
-                        let current_pix = ( self.current_scan_line - 1 ) * WIDTH as u16 + self.current_x as u16;
-                        if(pixVal != 0){
-                            // let's try own simple color scheme first?
-                            match pixVal {
-                                1 => {
-                                    self.output.mem[current_pix as usize] = (255, 0, 0); 
-                                },
-                                2 => {
-                                    self.output.mem[current_pix as usize] = (0, 255, 0);
-                                }
-                                3 => {
-                                    self.output.mem[current_pix as usize] = (0, 0, 255);
-                                }
-                                _ => {}
-
-                            }
-                        }else{
-                            self.output.mem[current_pix as usize] = (0, 0, 0); 
-                        }
-                       self.current_x += 1;
+                    4 => {
+                        // 16 bytes per tile, the low plane then the high plane 8 bytes on
+                        // these reads also let the mapper see which tiles are being fetched
+                        let offset = self.background_pattern_addr();
+                        self.tileOne = mem.read_vram_value(offset);
                     },
-                    257..=320 => {
-                        // TODO - for sprites
-                    }
-                    321..=336 => {
-                        // TODO for sprites on next scanline
+                    6 => {
+                        let offset = self.background_pattern_addr();
+                        self.tileTwo = mem.read_vram_value(offset + 8);
                     },
-                    337..=340 => {
-                        // dummy fetches
-                    }
-                    _ => {
-                        //panic!();
-                    }
+                    7 => self.increment_coarse_x(),
+                    _ => {}
+                }
+            }
+
+            if dot == 256 {
+                self.increment_y();
+            }
+            if dot == 257 {
+                self.copy_horizontal();
+            }
+            if !visible && (280..=304).contains(&dot) {
+                self.copy_vertical();
+            }
+
+            // sprites for the next line, the pre render line has nothing to find
+            if dot == 65 {
+                if visible {
+                    self.evaluate_sprites(mem);
+                } else {
+                    self.sprites_found = 0;
+                }
+            }
+            if (257..=320).contains(&dot) {
+                mem.set_oam_addr(0);
+                self.fetch_sprite(mem, dot - 257);
+            }
+        }
+
+        if visible && (1..=256).contains(&dot) {
+            self.draw_pixel(mem, dot - 1);
+        }
+    }
+
+    // one attribute byte covers a 4x4 tile area, 64 of them after each nametable's 960 tile bytes
+    fn attribute_addr(&self) -> usize {
+        let v = self.current_vram_address;
+        (0x23C0 | (v & (NAMETABLE_X | NAMETABLE_Y)) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07)) as usize
+    }
+
+    // two bits per 2x2 tile quadrant, top left in the low bits and bottom right in the high
+    fn attribute_palette(&self, attrib: u8) -> u8 {
+        let coarse_x = self.current_vram_address & COARSE_X;
+        let coarse_y = (self.current_vram_address & COARSE_Y) >> 5;
+        let shift = ((coarse_y & 0x02) << 1) | (coarse_x & 0x02);
+        (attrib >> shift) & 0x03
+    }
+
+    fn background_pattern_addr(&self) -> usize {
+        let fine_y = (self.current_vram_address & FINE_Y) >> 12;
+        self.PPUCTRL.bg_pattern_table_addr + self.next_tile_id as usize * 16 + fine_y as usize
+    }
+
+    fn shift_background(&mut self) {
+        if self.PPUMASK.show_bg != 0 {
+            self.bg_pattern_lo <<= 1;
+            self.bg_pattern_hi <<= 1;
+            self.bg_attrib_lo <<= 1;
+            self.bg_attrib_hi <<= 1;
+        }
+    }
+
+    fn load_background_shifters(&mut self) {
+        self.bg_pattern_lo = (self.bg_pattern_lo & 0xFF00) | self.tileOne as u16;
+        self.bg_pattern_hi = (self.bg_pattern_hi & 0xFF00) | self.tileTwo as u16;
+        // the whole tile uses the same palette
+        let attrib_lo = if self.pal_attrib_one & 0x01 != 0 { 0xFF } else { 0x00 };
+        let attrib_hi = if self.pal_attrib_one & 0x02 != 0 { 0xFF } else { 0x00 };
+        self.bg_attrib_lo = (self.bg_attrib_lo & 0xFF00) | attrib_lo;
+        self.bg_attrib_hi = (self.bg_attrib_hi & 0xFF00) | attrib_hi;
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.PPUCTRL.sprite_size != 0 { 16 } else { 8 }
+    }
+
+    // the sprites in range of the next line, the first 8 in OAM order go into secondary OAM
+    fn evaluate_sprites(&mut self, mem: &RAM) {
+        let row = self.current_scan_line;
+        let height = self.sprite_height();
+        let oam = mem.get_oam();
+
+        let in_range = |y: u8| row >= y as u16 && row < y as u16 + height;
+
+        self.secondary_oam = [0xFF; MAX_LINE_SPRITES * 4];
+        self.sprites_found = 0;
+        self.sprite_zero_found = false;
+        let mut n = 0;
+        while n < 64 && self.sprites_found < MAX_LINE_SPRITES {
+            let sprite = &oam[n * 4..n * 4 + 4];
+            if in_range(sprite[0]) {
+                let slot = self.sprites_found * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(sprite);
+                self.sprites_found += 1;
+                if n == 0 {
+                    self.sprite_zero_found = true;
+                }
+            }
+            n += 1;
+        }
+
+        // looking for a 9th the hardware moves on to the next byte of each entry as well as
+        // the next entry, so it checks tile numbers and x positions as if they were y
+        let mut m = 0;
+        while n < 64 {
+            if in_range(oam[n * 4 + m]) {
+                self.PPUSTATUS.overflow = 1;
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+        }
+    }
+
+    // 8 dots per sprite slot like the background, empty slots still fetch tile $FF
+    fn fetch_sprite(&mut self, mem: &mut RAM, step: u16) {
+        let slot = (step / 8) as usize;
+        if step == 0 {
+            self.sprite_count = self.sprites_found;
+            self.sprite_zero_on_line = self.sprite_zero_found;
+        }
+
+        match step % 8 {
+            0 => {
+                let attrib = self.secondary_oam[slot * 4 + 2];
+                let x = self.secondary_oam[slot * 4 + 3];
+                self.sprites[slot] = SpriteUnit { x: x, attrib: attrib, pattern_lo: 0, pattern_hi: 0 };
+            },
+            4 => {
+                let offset = self.sprite_pattern_addr(slot);
+                let value = mem.read_vram_value(offset);
+                if slot < self.sprite_count {
+                    self.sprites[slot].pattern_lo = value;
                 }
             },
-            241 => {
-               // post scanline
+            6 => {
+                let offset = self.sprite_pattern_addr(slot);
+                let value = mem.read_vram_value(offset + 8);
+                if slot < self.sprite_count {
+                    self.sprites[slot].pattern_hi = value;
+                }
             },
-            242..=261 => {
-                // vert blank
-                self.v_blank = true;
-                panic!();
+            _ => {}
+        }
+    }
+
+    fn sprite_pattern_addr(&self, slot: usize) -> usize {
+        // empty slots still fetch, from tile $FF
+        let (tile, row) = if slot >= self.sprite_count {
+            (0xFF, 0)
+        } else {
+            let y = self.secondary_oam[slot * 4] as u16;
+            let tile = self.secondary_oam[slot * 4 + 1] as usize;
+            let attrib = self.secondary_oam[slot * 4 + 2];
+
+            // wraps rather than panics if rendering came on after this line's evaluation
+            let mut row = self.current_scan_line.wrapping_sub(y) % self.sprite_height();
+            if attrib & SPRITE_FLIP_V != 0 {
+                row = self.sprite_height() - 1 - row;
             }
-            _=> {
+            (tile, row as usize)
+        };
+
+        // 8x16 sprites ignore PPUCTRL's table, bit 0 of the tile picks it and the rest an even/odd
+        // pair, top half then bottom, so flipping vertically swaps the two tiles over as well
+        if self.sprite_height() == 16 {
+            let table = PAT_TABLE_ADDR[tile & 0x01];
+            table + ((tile & 0xFE) + row / 8) * 16 + row % 8
+        } else {
+            self.PPUCTRL.sprite_pattern_table_addr + tile * 16 + row
+        }
+    }
+
+    // background pixel (0 is transparent) and the palette it uses
+    fn draw_bg(&self, x: u16) -> (u8, u8) {
+        if self.PPUMASK.show_bg == 0 || (x < 8 && self.PPUMASK.show_bg_left == 0) {
+            return (0, 0);
+        }
+        // fine x picks which bit of the shift registers is this pixel
+        let mux = 0x8000 >> self.fine_x_scroll;
+        let lo = ((self.bg_pattern_lo & mux) != 0) as u8;
+        let hi = ((self.bg_pattern_hi & mux) != 0) as u8;
+        let pal_lo = ((self.bg_attrib_lo & mux) != 0) as u8;
+        let pal_hi = ((self.bg_attrib_hi & mux) != 0) as u8;
+        ((hi << 1) | lo, (pal_hi << 1) | pal_lo)
+    }
+
+    // the first sprite with a solid pixel here wins, lower OAM entries go in front
+    // gives back the pixel, the attributes and the slot it came from
+    fn draw_fg(&self, x: u16) -> Option<(u8, u8, usize)> {
+        if self.PPUMASK.show_spr == 0 || (x < 8 && self.PPUMASK.show_spr_left == 0) {
+            return None;
+        }
+        for (slot, sprite) in self.sprites[..self.sprite_count].iter().enumerate() {
+            let col = x.wrapping_sub(sprite.x as u16);
+            if col >= 8 {
+                continue;
+            }
+            let bit = if sprite.attrib & SPRITE_FLIP_H != 0 { col } else { 7 - col };
+            let lo = (sprite.pattern_lo >> bit) & 1;
+            let hi = (sprite.pattern_hi >> bit) & 1;
+            let pixel = (hi << 1) | lo;
+            if pixel != 0 {
+                return Some((pixel, sprite.attrib, slot));
             }
         }
+        None
+    }
 
-        
-        self.current_cycle = (self.current_cycle + 1) % 340;
-        if self.current_cycle == 0 {
-            self.current_scan_line = (self.current_scan_line + 1) % 241;
+    // solid sprite 0 over solid background, never in the last column
+    // clipped or hidden pixels were already left out as transparent
+    fn check_sprite_zero_hit(&mut self, x: u16) {
+        if self.sprite_zero_on_line && x != 255 {
+            self.PPUSTATUS.sprite_hit = 1;
         }
+    }
+
+    fn draw_pixel(&mut self, mem: &RAM, x: u16) {
+        let (pixVal, pallette) = self.draw_bg(x);
 
+        // sprites go behind the background only where it's solid
+        // color 0 of any palette shows the backdrop at $3F00
+        let sprite = self.draw_fg(x);
+        if let Some((_, _, slot)) = sprite {
+            if slot == 0 && pixVal != 0 {
+                self.check_sprite_zero_hit(x);
+            }
+        }
+
+        let pallette_idx = match sprite {
+            Some((spr_pixel, attrib, _)) if pixVal == 0 || attrib & SPRITE_BEHIND_BG == 0 => {
+                (0x10 + (attrib & SPRITE_PALLETTE) * 4 + spr_pixel) as usize
+            }
+            _ if pixVal != 0 => (pallette * 4 + pixVal) as usize,
+            // with rendering off the backdrop is whichever palette entry v points at, if any
+            _ if !self.rendering_enabled() && (self.current_vram_address & 0x3FFF) as usize >= PALLETE_RAM_INDICES_START => {
+                (self.current_vram_address & 0x1F) as usize
+            }
+            _ => 0,
+        };
+        let mut color = mem.get_pallette_color(pallette_idx) & 0x3F;
+        // greyscale keeps just the brightness column of the palette
+        if self.PPUMASK.greyScale != 0 {
+            color &= 0x30;
+        }
+
+        let current_pix = self.current_scan_line * WIDTH as u16 + x;
+        self.output.mem[current_pix as usize] = self.pallette.get_color(color, self.emphasis());
+    }
+
+    fn emphasis(&self) -> u8 {
+        self.PPUMASK.emphasize_red | (self.PPUMASK.emphasize_green << 1) | (self.PPUMASK.emphasize_blue << 2)
+    }
+
+    // coarse x wraps into the next nametable across
+    fn increment_coarse_x(&mut self) {
+        if self.current_vram_address & COARSE_X == COARSE_X {
+            self.current_vram_address &= !COARSE_X;
+            self.current_vram_address ^= NAMETABLE_X;
+        } else {
+            self.current_vram_address += 1;
+        }
+    }
+
+    // fine y first, then coarse y, which wraps into the next nametable down after row 29
+    // rows 30 and 31 are attribute data, scrolling into them wraps without switching
+    fn increment_y(&mut self) {
+        let mut v = self.current_vram_address;
+        if v & FINE_Y != FINE_Y {
+            v += 0x1000;
+        } else {
+            v &= !FINE_Y;
+            let mut coarse_y = (v & COARSE_Y) >> 5;
+            if coarse_y == 29 {
+                coarse_y = 0;
+                v ^= NAMETABLE_Y;
+            } else if coarse_y == 31 {
+                coarse_y = 0;
+            } else {
+                coarse_y += 1;
+            }
+            v = (v & !COARSE_Y) | (coarse_y << 5);
+        }
+        self.current_vram_address = v;
+    }
+
+    fn copy_horizontal(&mut self) {
+        self.current_vram_address = (self.current_vram_address & !HORIZONTAL_BITS) | (self.temp_vram_address & HORIZONTAL_BITS);
+    }
+
+    fn copy_vertical(&mut self) {
+        self.current_vram_address = (self.current_vram_address & !VERTICAL_BITS) | (self.temp_vram_address & VERTICAL_BITS);
+    }
+
+    // $2007 accesses move v on by 1 or 32, except while rendering when they
+    // bump coarse x and y together like the rendering increments do
+    fn increment_vram_address(&mut self) {
+        let rendering_line = self.current_scan_line == self.pre_render_line() || self.current_scan_line < POST_RENDER_LINE;
+        if self.rendering_enabled() && rendering_line {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            let incr = self.PPUCTRL.VRAM_address_increment as u16;
+            self.current_vram_address = self.current_vram_address.wrapping_add(incr) & 0x7FFF;
+        }
     }
 
     pub fn updatePpuCtrl(&mut self, byte_val: u8) {
-        println!("{:#b}", byte_val);
-        let name_table_idx = byte_val & 0x03;
-        self.PPUCTRL.nametableAddress = NAME_TABLE_ADDRS[name_table_idx as usize];
+        // the nametable select goes into t, rendering picks it up from there
+        let name_table_idx = (byte_val & 0x03) as u16;
+        self.temp_vram_address = (self.temp_vram_address & !(NAMETABLE_X | NAMETABLE_Y)) | (name_table_idx << 10);
         let vram_incr = get_bit(byte_val, 2);
         self.PPUCTRL.VRAM_address_increment = VRAM_INCRS[vram_incr as usize];
 
@@ -255,47 +691,63 @@ impl PPU {
         self.PPUMASK.emphasize_blue = get_bit(byte_val, 7);
     }
 
+    // reading clears vblank, and reading right as it starts means it never gets set this frame
     pub fn readPpuStatus(&mut self) {
-        self.PPUSCROLL.write_byte = 0;
-        self.PPUADDR.write_byte = 0;
+        self.first_second_write_toggle = 0;
+        self.PPUSTATUS.vert_blank_started = 0;
+        if self.current_scan_line == self.vblank_line() && self.current_cycle == 1 {
+            self.suppress_vblank = true;
+        }
     }
 
     pub fn updatePpuScroll(&mut self, byte_val: u8) {
-        // two writes to this write the address that will be read when checking the nametable to render
-        // starting in top left of screen
-        if self.PPUSCROLL.write_byte == 0 {
-            self.PPUSCROLL.horiz_offset = byte_val;
+        // two writes, x then y, both go into t and only reach v when rendering copies them over
+        let value = byte_val as u16;
+        if self.first_second_write_toggle == 0 {
+            self.temp_vram_address = (self.temp_vram_address & !COARSE_X) | (value >> 3);
+            self.fine_x_scroll = byte_val & 0x07;
+            self.first_second_write_toggle = 1;
         } else {
-            self.PPUSCROLL.vert_offset = byte_val;
+            self.temp_vram_address = (self.temp_vram_address & !(COARSE_Y | FINE_Y)) | ((value & 0xF8) << 2) | ((value & 0x07) << 12);
+            self.first_second_write_toggle = 0;
         }
     }
 
     pub fn updatePpuAddr(&mut self, byte_val: u8) {
-        // two writes to this write the address any writes to updatePpuAddr will write to
-        // upper byte / big endian first
-        if self.PPUADDR.write_byte == 0 {
-            self.PPUADDR.address = 0;
-            self.PPUADDR.address = ((byte_val as u16) << 8) as usize;
-            self.PPUADDR.write_byte += 1;
+        // two writes, upper byte / big endian first, shares its toggle and t with $2005
+        // the second write copies t into v straight away
+        let value = byte_val as u16;
+        if self.first_second_write_toggle == 0 {
+            self.temp_vram_address = (self.temp_vram_address & 0x00FF) | ((value & 0x3F) << 8);
+            self.first_second_write_toggle = 1;
         } else {
-            self.PPUADDR.address |= ((byte_val as u16) as usize);
+            self.temp_vram_address = (self.temp_vram_address & 0xFF00) | value;
+            self.current_vram_address = self.temp_vram_address;
+            self.first_second_write_toggle = 0;
         }
     }
 
-    pub fn updateOAMAddr(&mut self, byte_val: u8) {
-        todo!();
-    }
-
-    pub fn updateOAMData(&mut self, byte_val: u8) {
-        todo!();
+    // OAMADDR lives on the bus side since dma starts from it too
+    pub fn updateOAMAddr(&mut self, mem: &mut RAM, byte_val: u8) {
+        mem.set_oam_addr(byte_val);
     }
 
-    pub fn draw_bg(&mut self) {
-        todo!();
+    // writes go where OAMADDR points and move it on, dma is quicker but this works too
+    pub fn updateOAMData(&mut self, mem: &mut RAM, byte_val: u8) {
+        let addr = mem.get_oam_addr();
+        mem.write_oam(addr, byte_val);
+        mem.set_oam_addr(addr.wrapping_add(1));
     }
 
-    pub fn draw_fg(&mut self) {
-        todo!();
+    // the attribute byte only has 5 real bits, the unused ones read back as 0
+    fn read_oam_data(&self, mem: &RAM) -> u8 {
+        let addr = mem.get_oam_addr();
+        let value = mem.get_oam()[addr as usize];
+        if addr & 0x03 == 2 {
+            value & 0xE3
+        } else {
+            value
+        }
     }
 
     pub fn get_output_image(&self) -> &[(u8,u8,u8)] {
@@ -303,12 +755,11 @@ impl PPU {
     }
 
     pub fn can_scan_out(&self) -> bool {
-        self.v_blank
+        self.PPUSTATUS.vert_blank_started != 0
     }
 }
 
 struct ppuCtrl {
-    nametableAddress: usize,
     VRAM_address_increment: usize,
     sprite_pattern_table_addr: usize,
     bg_pattern_table_addr: usize,
@@ -319,7 +770,6 @@ struct ppuCtrl {
 impl Default for ppuCtrl {
     fn default() -> Self {
         ppuCtrl {
-            nametableAddress: NAME_TABLE_ADDRS[0],
             VRAM_address_increment: VRAM_INCRS[0],
             sprite_pattern_table_addr: PAT_TABLE_ADDR[0],
             bg_pattern_table_addr: PAT_TABLE_ADDR[0],
@@ -348,19 +798,6 @@ struct ppuStatus {
     vert_blank_started: u8,
 }
 
-#[derive(Default)]
-struct ppuScroll {
-    horiz_offset: u8,
-    vert_offset: u8,
-    write_byte: u8,
-}
-
-#[derive(Default)]
-struct ppuAddr {
-    address: usize,
-    write_byte: u8,
-}
-
 pub struct output_image {
     pub mem : [(u8, u8, u8); (WIDTH * HEIGHT) as usize]
 }
@@ -377,6 +814,7 @@ impl Default for output_image {
 pub mod Test {
 
     use super::*;
+    use super::super::pallette::color_pallette;
     use crate::memory::RAM;
 
     #[test]
@@ -418,4 +856,469 @@ pub mod Test {
 
         assert_eq!(test_val, 255);
     }
+
+    #[test]
+    pub fn loopy_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        let mut ppu: PPU = PPU::default();
+
+        // the write sequence from the nesdev scrolling doc
+        test_memory.write_mem_value(PPUCTRL as u16, 0x00);
+        test_memory.read_mem_value(PPUSTATUS as u16);
+        ppu.run(&mut test_memory);
+        test_memory.write_mem_value(PPUSCROLL as u16, 0x7D);
+        ppu.run(&mut test_memory);
+        assert_eq!(ppu.temp_vram_address, 0x000F);
+        assert_eq!(ppu.fine_x_scroll, 5);
+        test_memory.write_mem_value(PPUSCROLL as u16, 0x5E);
+        ppu.run(&mut test_memory);
+        assert_eq!(ppu.temp_vram_address, 0x616F);
+        test_memory.write_mem_value(PPUADDR as u16, 0x3D);
+        ppu.run(&mut test_memory);
+        assert_eq!(ppu.temp_vram_address, 0x3D6F);
+        test_memory.write_mem_value(PPUADDR as u16, 0xF0);
+        ppu.run(&mut test_memory);
+        assert_eq!(ppu.current_vram_address, 0x3DF0);
+        assert_eq!(ppu.first_second_write_toggle, 0);
+
+        // coarse x into the next nametable across, coarse y past row 29 into the one below
+        ppu.current_vram_address = 0x001F;
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.current_vram_address, NAMETABLE_X);
+        ppu.current_vram_address = FINE_Y | (29 << 5);
+        ppu.increment_y();
+        assert_eq!(ppu.current_vram_address, NAMETABLE_Y);
+        ppu.current_vram_address = FINE_Y | (31 << 5) | NAMETABLE_Y;
+        ppu.increment_y();
+        assert_eq!(ppu.current_vram_address, NAMETABLE_Y);
+
+        ppu.temp_vram_address = 0x7FFF;
+        ppu.current_vram_address = 0;
+        ppu.copy_horizontal();
+        assert_eq!(ppu.current_vram_address, HORIZONTAL_BITS);
+        ppu.copy_vertical();
+        assert_eq!(ppu.current_vram_address, 0x7FFF);
+    }
+
+    #[test]
+    pub fn pallette_render_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        let mut ppu: PPU = PPU::default();
+        test_memory.write_vram_value(0x3F00, 0x0F);
+        test_memory.write_vram_value(0x3F03, 0x21);
+
+        ppu.current_scan_line = 0;
+        ppu.updatePpuMask(0x0A);
+        ppu.bg_pattern_lo = 0x8000;
+        ppu.bg_pattern_hi = 0x8000;
+        ppu.draw_pixel(&test_memory, 0);
+        assert_eq!(ppu.get_output_image()[0], color_pallette[0x21]);
+
+        // transparent shows the backdrop
+        ppu.bg_pattern_lo = 0;
+        ppu.bg_pattern_hi = 0;
+        ppu.draw_pixel(&test_memory, 1);
+        assert_eq!(ppu.get_output_image()[1], color_pallette[0x0F]);
+    }
+
+    #[test]
+    pub fn attribute_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: RAM = RAM::new(&Header::default()).unwrap();
+        let mut ppu: PPU = PPU::default();
+
+        // tile (5, 6) of the second nametable is the bottom left of attribute byte $27C9
+        ppu.current_vram_address = NAMETABLE_X | (6 << 5) | 5;
+        assert_eq!(ppu.attribute_addr(), 0x27C9);
+        assert_eq!(ppu.attribute_palette(0b11_10_01_00), 0b10);
+        // and (6, 4) the top right of the same byte
+        ppu.current_vram_address = NAMETABLE_X | (4 << 5) | 6;
+        assert_eq!(ppu.attribute_addr(), 0x27C9);
+        assert_eq!(ppu.attribute_palette(0b11_10_01_00), 0b01);
+
+        // palette 2 color 1 lives at $3F09
+        test_memory.write_vram_value(0x3F09, 0x2A);
+        ppu.pal_attrib_one = 2;
+        ppu.tileOne = 0xFF;
+        ppu.load_background_shifters();
+        // shifted up into the top byte, where the tile being drawn sits
+        ppu.updatePpuMask(0x0A);
+        for _ in 0..8 {
+            ppu.shift_background();
+        }
+        ppu.current_scan_line = 0;
+        ppu.draw_pixel(&test_memory, 0);
+        assert_eq!(ppu.get_output_image()[0], color_pallette[0x2A]);
+    }
+
+    #[test]
+    pub fn sprite_tests() {
+        use crate::cartridge::{Header, CHR_RAM_DEFAULT};
+
+        let mut header = Header::default();
+        header.num_chr_blocks = 0;
+        header.chr_ram_size = CHR_RAM_DEFAULT;
+        let mut test_memory: RAM = RAM::new(&header).unwrap();
+        let mut ppu: PPU = PPU::default();
+
+        // tile 1 has just its top left pixel set, color 1
+        test_memory.write_vram_value(0x0010, 0x80);
+        test_memory.write_vram_value(0x3F15, 0x2C);
+        test_memory.write_vram_value(0x3F19, 0x16);
+
+        // y, tile, attributes, x through OAMADDR/OAMDATA
+        let sprites = [
+            [10, 1, 0x01, 20],
+            // flipped across, so the pixel shows up on its right hand side
+            [10, 1, 0x02 | SPRITE_FLIP_H, 40],
+        ];
+        test_memory.write_mem_value(OAMADDR as u16, 0);
+        ppu.run(&mut test_memory);
+        for sprite in sprites.iter() {
+            for &byte in sprite.iter() {
+                test_memory.write_mem_value(OAMDATA as u16, byte);
+                ppu.run(&mut test_memory);
+            }
+        }
+        test_memory.write_mem_value(OAMADDR as u16, 6);
+        ppu.run(&mut test_memory);
+        assert_eq!(test_memory.read_mem_value(OAMDATA as u16), 0x42);
+        // unused attribute bits read back as 0
+        test_memory.write_mem_value(OAMDATA as u16, 0xFF);
+        ppu.run(&mut test_memory);
+        test_memory.write_mem_value(OAMADDR as u16, 6);
+        ppu.run(&mut test_memory);
+        assert_eq!(test_memory.read_mem_value(OAMDATA as u16), 0xE3);
+        test_memory.write_mem_value(OAMDATA as u16, 0x02 | SPRITE_FLIP_H);
+        ppu.run(&mut test_memory);
+
+        // every other sprite off the bottom of the screen
+        for idx in 8..256 {
+            test_memory.write_oam(idx as u8, 0xFF);
+        }
+
+        test_memory.write_mem_value(PPUMASK as u16, 0x18);
+        // sprites with y = 10 start on the 11th row
+        for _ in 0..(14 * DOTS_PER_LINE) {
+            ppu.run(&mut test_memory);
+        }
+        let image = ppu.get_output_image();
+        assert_eq!(image[11 * WIDTH as usize + 20], color_pallette[0x2C]);
+        assert_eq!(image[11 * WIDTH as usize + 21], color_pallette[0x00]);
+        assert_eq!(image[11 * WIDTH as usize + 47], color_pallette[0x16]);
+        assert_eq!(image[10 * WIDTH as usize + 20], color_pallette[0x00]);
+        assert_eq!(image[12 * WIDTH as usize + 20], color_pallette[0x00]);
+    }
+
+    #[test]
+    pub fn status_tests() {
+        use crate::cartridge::{Header, CHR_RAM_DEFAULT};
+
+        let mut header = Header::default();
+        header.num_chr_blocks = 0;
+        header.chr_ram_size = CHR_RAM_DEFAULT;
+
+        // tile 0 has a solid top row so every eighth background line is opaque,
+        // tile 1 just its top left pixel
+        let setup = |sprites: &[[u8; 4]], mask: u8| {
+            // boxed, a frame's worth of these doesn't fit on the test thread's stack
+            let mut mem: Box<RAM> = Box::new(RAM::new(&header).unwrap());
+            let mut ppu: Box<PPU> = Box::default();
+            mem.write_vram_value(0x0000, 0xFF);
+            mem.write_vram_value(0x0010, 0x80);
+            for idx in 0..256 {
+                mem.write_oam(idx as u8, 0xFF);
+            }
+            for (n, sprite) in sprites.iter().enumerate() {
+                for (m, &byte) in sprite.iter().enumerate() {
+                    mem.write_oam((n * 4 + m) as u8, byte);
+                }
+            }
+            mem.write_mem_value(PPUMASK as u16, mask);
+            ppu.run(&mut mem);
+            (mem, ppu)
+        };
+        let status_after = |mem: &mut RAM, ppu: &mut PPU, lines: usize| {
+            for _ in 0..(lines * DOTS_PER_LINE as usize) {
+                ppu.run(mem);
+            }
+            mem.read_mem_value(PPUSTATUS as u16)
+        };
+
+        // sprite 0 row 0 lands on background row 8
+        let (mut mem, mut ppu) = setup(&[[7, 1, 0, 20]], 0x1E);
+        assert_eq!(status_after(&mut mem, &mut ppu, 8) & STATUS_SPRITE_HIT, 0);
+        assert_eq!(status_after(&mut mem, &mut ppu, 3) & STATUS_SPRITE_HIT, STATUS_SPRITE_HIT);
+
+        // the same sprite further down OAM doesn't count
+        let (mut mem, mut ppu) = setup(&[[0xF0, 1, 0, 0], [7, 1, 0, 20]], 0x1E);
+        assert_eq!(status_after(&mut mem, &mut ppu, 11) & STATUS_SPRITE_HIT, 0);
+
+        // nor does it in the clipped left column, or at x = 255
+        let (mut mem, mut ppu) = setup(&[[7, 1, 0, 2]], 0x18);
+        assert_eq!(status_after(&mut mem, &mut ppu, 11) & STATUS_SPRITE_HIT, 0);
+        let (mut mem, mut ppu) = setup(&[[7, 1, 0, 2]], 0x1E);
+        assert_eq!(status_after(&mut mem, &mut ppu, 11) & STATUS_SPRITE_HIT, STATUS_SPRITE_HIT);
+        let (mut mem, mut ppu) = setup(&[[7, 1, 0, 255]], 0x1E);
+        assert_eq!(status_after(&mut mem, &mut ppu, 11) & STATUS_SPRITE_HIT, 0);
+
+        // nine sprites on a line overflow
+        let (mut mem, mut ppu) = setup(&[[20, 1, 0, 0]; 9], 0x18);
+        assert_eq!(status_after(&mut mem, &mut ppu, 20) & STATUS_OVERFLOW, 0);
+        assert_eq!(status_after(&mut mem, &mut ppu, 3) & STATUS_OVERFLOW, STATUS_OVERFLOW);
+
+        // eight don't, but after them the ninth's tile number gets compared as a y
+        let mut sprites = [[20, 1, 0, 0]; 10];
+        sprites[8] = [0xF0, 0xF0, 0xF0, 0xF0];
+        sprites[9] = [0xF0, 0xF0, 0xF0, 0xF0];
+        let (mut mem, mut ppu) = setup(&sprites, 0x18);
+        assert_eq!(status_after(&mut mem, &mut ppu, 30) & STATUS_OVERFLOW, 0);
+        sprites[9] = [0xF0, 18, 0xF0, 0xF0];
+        let (mut mem, mut ppu) = setup(&sprites, 0x18);
+        assert_eq!(status_after(&mut mem, &mut ppu, 30) & STATUS_OVERFLOW, STATUS_OVERFLOW);
+
+        // the low bits are the last thing written to the ppu
+        let (mut mem, mut ppu) = setup(&[], 0x00);
+        mem.write_mem_value(PPUSCROLL as u16, 0x5A);
+        assert_eq!(status_after(&mut mem, &mut ppu, 1), 0x1A);
+    }
+
+    #[test]
+    pub fn timing_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: Box<RAM> = Box::new(RAM::new(&Header::default()).unwrap());
+        let mut ppu: Box<PPU> = Box::default();
+        let vblank_start = ppu.vblank_line() as usize * DOTS_PER_LINE as usize + 1;
+
+        // up to just before vblank starts
+        for _ in 0..vblank_start {
+            ppu.run(&mut test_memory);
+        }
+        // looking without a cpu read, which would stop it from being set at all
+        let status = PPUSTATUS - PPU_REGISTERS_START;
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (ppu.vblank_line(), 1));
+        assert_eq!(test_memory.get_ppu_reg(status) & STATUS_VBLANK, 0);
+        ppu.run(&mut test_memory);
+        assert_eq!(test_memory.read_mem_value(PPUSTATUS as u16) & STATUS_VBLANK, STATUS_VBLANK);
+        // reading it cleared it
+        ppu.run(&mut test_memory);
+        assert_eq!(test_memory.read_mem_value(PPUSTATUS as u16) & STATUS_VBLANK, 0);
+        assert!(!test_memory.take_nmi());
+
+        // turning nmi on during vblank still gets one, a couple of dots later
+        test_memory.write_mem_value(PPUCTRL as u16, 0x80);
+        ppu.run(&mut test_memory);
+        assert!(!test_memory.take_nmi());
+        ppu.run(&mut test_memory);
+        assert!(!test_memory.take_nmi());
+        // ... or it would, vblank was already cleared by the read
+        test_memory.write_mem_value(PPUCTRL as u16, 0x00);
+        ppu.run(&mut test_memory);
+
+        // the pre render line clears it again, then a whole frame later it's back
+        let frame = ppu.region.scanlines() as usize * DOTS_PER_LINE as usize;
+        test_memory.write_mem_value(PPUCTRL as u16, 0x80);
+        for _ in 0..(frame - 4) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(ppu.get_frame(), 1);
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (ppu.vblank_line(), 2));
+        assert!(!test_memory.take_nmi());
+        ppu.run(&mut test_memory);
+        ppu.run(&mut test_memory);
+        assert!(test_memory.take_nmi());
+
+        // reading PPUSTATUS right before vblank starts loses the flag and the nmi for that frame
+        for _ in 0..(frame - 3) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!((ppu.get_scanline(), ppu.get_dot()), (ppu.vblank_line(), 1));
+        test_memory.read_mem_value(PPUSTATUS as u16);
+        for _ in 0..4 {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(test_memory.read_mem_value(PPUSTATUS as u16) & STATUS_VBLANK, 0);
+        assert!(!test_memory.take_nmi());
+
+        // with rendering on, odd frames are a dot short
+        test_memory.write_mem_value(PPUCTRL as u16, 0x00);
+        test_memory.write_mem_value(PPUMASK as u16, 0x08);
+        while ppu.get_scanline() != 0 {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(ppu.get_frame(), 3);
+        for _ in 0..(frame - 1) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!((ppu.get_frame(), ppu.get_scanline(), ppu.get_dot()), (4, 0, 0));
+        for _ in 0..(frame - 1) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!((ppu.get_frame(), ppu.get_scanline(), ppu.get_dot()), (4, ppu.pre_render_line(), DOTS_PER_LINE - 1));
+    }
+
+    #[test]
+    pub fn ppu_data_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: Box<RAM> = Box::new(RAM::new(&Header::default()).unwrap());
+        let mut ppu: Box<PPU> = Box::default();
+        test_memory.write_vram_value(0x2005, 0x11);
+        test_memory.write_vram_value(0x2006, 0x22);
+        test_memory.write_vram_value(0x2F01, 0x55);
+        test_memory.write_vram_value(0x3F01, 0x2A);
+
+        let write = |mem: &mut RAM, ppu: &mut PPU, reg: usize, value: u8| {
+            mem.write_mem_value(reg as u16, value);
+            ppu.run(mem);
+        };
+        let read = |mem: &mut RAM, ppu: &mut PPU| {
+            let value = mem.read_mem_value(PPUDATA as u16);
+            ppu.run(mem);
+            value
+        };
+
+        // a read behind, the first one gets whatever was in the buffer
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x20);
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x05);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x00);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x11);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x22);
+
+        // palettes come straight back, and fill the buffer from the nametable under them
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x3F);
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x01);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x2A);
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x00);
+        write(&mut test_memory, &mut ppu, PPUADDR, 0x00);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x55);
+
+        // v is 15 bits but the ppu bus only 14
+        ppu.current_vram_address = 0x6005;
+        read(&mut test_memory, &mut ppu);
+        assert_eq!(read(&mut test_memory, &mut ppu), 0x11);
+        assert_eq!(ppu.current_vram_address, 0x6007);
+    }
+
+    #[test]
+    pub fn mask_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: Box<RAM> = Box::new(RAM::new(&Header::default()).unwrap());
+        let mut ppu: Box<PPU> = Box::default();
+        test_memory.write_vram_value(0x3F00, 0x0F);
+        test_memory.write_vram_value(0x3F03, 0x21);
+        test_memory.write_vram_value(0x3F07, 0x16);
+        ppu.bg_pattern_lo = 0xFFFF;
+        ppu.bg_pattern_hi = 0xFFFF;
+
+        // the left column is hidden unless asked for
+        ppu.updatePpuMask(0x08);
+        ppu.draw_pixel(&test_memory, 7);
+        ppu.draw_pixel(&test_memory, 8);
+        assert_eq!(ppu.get_output_image()[7], color_pallette[0x0F]);
+        assert_eq!(ppu.get_output_image()[8], color_pallette[0x21]);
+
+        // greyscale drops the hue, emphasis picks another set of colors
+        ppu.updatePpuMask(0x09);
+        ppu.draw_pixel(&test_memory, 8);
+        assert_eq!(ppu.get_output_image()[8], color_pallette[0x20]);
+        ppu.updatePpuMask(0x28);
+        ppu.draw_pixel(&test_memory, 8);
+        assert_eq!(ppu.get_output_image()[8], ppu.pallette.get_color(0x21, 0x01));
+        assert_ne!(ppu.get_output_image()[8], color_pallette[0x21]);
+
+        // nothing drawn with rendering off, just the backdrop or the palette entry v is on
+        ppu.updatePpuMask(0x00);
+        ppu.draw_pixel(&test_memory, 8);
+        assert_eq!(ppu.get_output_image()[8], color_pallette[0x0F]);
+        ppu.current_vram_address = 0x3F07;
+        ppu.draw_pixel(&test_memory, 8);
+        assert_eq!(ppu.get_output_image()[8], color_pallette[0x16]);
+    }
+
+    #[test]
+    pub fn tall_sprite_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: Box<RAM> = Box::new(RAM::new(&Header::default()).unwrap());
+        let mut ppu: Box<PPU> = Box::default();
+        // and PPUCTRL's sprite table is ignored
+        ppu.updatePpuCtrl(0x28);
+
+        for idx in 0..256 {
+            test_memory.write_oam(idx as u8, 0xFF);
+        }
+        // tiles $02 and $03 from $0000
+        let sprite = [10, 0x02, 0x00, 0];
+        for (idx, &byte) in sprite.iter().enumerate() {
+            test_memory.write_oam(idx as u8, byte);
+        }
+
+        // in range for 16 rows
+        ppu.current_scan_line = 25;
+        ppu.evaluate_sprites(&test_memory);
+        assert_eq!(ppu.sprites_found, 1);
+        ppu.current_scan_line = 26;
+        ppu.evaluate_sprites(&test_memory);
+        assert_eq!(ppu.sprites_found, 0);
+
+        ppu.current_scan_line = 10;
+        ppu.evaluate_sprites(&test_memory);
+        ppu.sprite_count = ppu.sprites_found;
+        assert_eq!(ppu.sprite_pattern_addr(0), 0x0020);
+        ppu.current_scan_line = 19;
+        assert_eq!(ppu.sprite_pattern_addr(0), 0x0031);
+
+        // odd tiles come from $1000, flipped the bottom tile's last row is on top
+        test_memory.write_oam(1, 0x03);
+        test_memory.write_oam(2, SPRITE_FLIP_V);
+        ppu.current_scan_line = 10;
+        ppu.evaluate_sprites(&test_memory);
+        assert_eq!(ppu.sprite_pattern_addr(0), 0x1037);
+        ppu.current_scan_line = 25;
+        assert_eq!(ppu.sprite_pattern_addr(0), 0x1020);
+
+        // empty slots fetch tile $FF, which makes it the $FE/$FF pair from $1000
+        assert_eq!(ppu.sprite_pattern_addr(1), 0x1FE0);
+    }
+
+    #[test]
+    pub fn region_timing_tests() {
+        use crate::cartridge::Header;
+
+        let mut test_memory: Box<RAM> = Box::new(RAM::new(&Header::default()).unwrap());
+        let status = PPUSTATUS - PPU_REGISTERS_START;
+
+        // PAL has 312 lines and never skips a dot
+        let mut ppu: Box<PPU> = Box::default();
+        ppu.set_region(Region::PAL);
+        test_memory.write_mem_value(PPUMASK as u16, 0x08);
+        for _ in 0..(2 * 312 * DOTS_PER_LINE as usize) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!((ppu.get_frame(), ppu.get_scanline(), ppu.get_dot()), (2, 0, 0));
+        for _ in 0..(241 * DOTS_PER_LINE as usize + 2) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(test_memory.get_ppu_reg(status) & STATUS_VBLANK, STATUS_VBLANK);
+
+        // the Dendy's vblank starts 50 lines later
+        let mut ppu: Box<PPU> = Box::default();
+        ppu.set_region(Region::Dendy);
+        for _ in 0..(241 * DOTS_PER_LINE as usize + 2) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(test_memory.get_ppu_reg(status) & STATUS_VBLANK, 0);
+        for _ in 0..(50 * DOTS_PER_LINE as usize) {
+            ppu.run(&mut test_memory);
+        }
+        assert_eq!(test_memory.get_ppu_reg(status) & STATUS_VBLANK, STATUS_VBLANK);
+    }
 }
@@ -0,0 +1,149 @@
+// romdb.rs - games we know the right header for, keyed on the crc32 or sha-1 of prg + chr
+// plenty of iNES dumps in the wild have the wrong mapper, mirroring or battery bit, these win over the header
+use crate::cartridge::{Cartridge, Header, Mirroring, Region};
+use crate::checksum::{self, Sha1};
+
+pub struct GameEntry {
+    pub crc32: u32,
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub region: Region,
+    pub mapper: u8,
+    // None where the mapper controls it and the header bit doesn't matter
+    pub mirroring: Option<Mirroring>,
+    pub has_battery: bool,
+}
+
+// only entries checked against known good dumps go in here, they're looked at before the database
+const GAMES: &[GameEntry] = &[
+    GameEntry {
+        crc32: 0x3337_EC46,
+        sha1: "ea343f4e445a9050d4b4fbac2c77d0693b1d0922",
+        title: "Super Mario Bros.",
+        region: Region::NTSC,
+        mapper: 0,
+        mirroring: Some(Mirroring::Vertical),
+        has_battery: false,
+    },
+];
+
+// generated by build.rs from data/nes20db.xml, empty when that isn't there
+include!(concat!(env!("OUT_DIR"), "/romdb_games.rs"));
+
+pub struct RomChecksums {
+    pub crc32: u32,
+    pub sha1: String,
+}
+
+// header and trainer aren't part of the game as far as the database is concerned
+pub fn checksums(cart: &Cartridge) -> RomChecksums {
+    let crc = checksum::crc32_update(checksum::crc32(&cart.prg), &cart.chr);
+    let mut sha = Sha1::default();
+    sha.update(&cart.prg);
+    sha.update(&cart.chr);
+
+    RomChecksums {
+        crc32: crc,
+        sha1: checksum::to_hex(&sha.finish()),
+    }
+}
+
+fn find<'a>(games: &'a [GameEntry], sums: &RomChecksums) -> Option<&'a GameEntry> {
+    games
+        .iter()
+        .find(|game| game.crc32 == sums.crc32 || (!game.sha1.is_empty() && game.sha1 == sums.sha1))
+}
+
+pub fn lookup(sums: &RomChecksums) -> Option<&'static GameEntry> {
+    find(GAMES, sums).or_else(|| find(DB_GAMES, sums))
+}
+
+// fixes up the header to match the database, returns what it changed
+pub fn correct_header(game: &GameEntry, header: &mut Header) -> Vec<String> {
+    let mut corrections = Vec::new();
+
+    if header.mapper != game.mapper {
+        corrections.push(format!("mapper {} -> {}", header.mapper, game.mapper));
+        header.mapper = game.mapper;
+    }
+
+    if let Some(mirroring) = game.mirroring {
+        let current = header.mirroring();
+        if current != mirroring {
+            match mirroring {
+                Mirroring::FourScreen => header.four_screen = true,
+                other => {
+                    header.four_screen = false;
+                    header.mirror = match other {
+                        Mirroring::Vertical => 1,
+                        Mirroring::SingleLower => 2,
+                        Mirroring::SingleUpper => 3,
+                        _ => 0,
+                    };
+                }
+            }
+            if header.mirroring() != current {
+                corrections.push(format!("mirroring {:?} -> {:?}", current, header.mirroring()));
+            }
+        }
+    }
+
+    if header.has_battery != game.has_battery {
+        corrections.push(format!("battery {} -> {}", header.has_battery, game.has_battery));
+        header.has_battery = game.has_battery;
+    }
+
+    if header.region != game.region {
+        corrections.push(format!("region {:?} -> {:?}", header.region, game.region));
+        header.region = game.region;
+    }
+
+    corrections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn romdb_tests() {
+        let cart = Cartridge {
+            header: Header::default(),
+            prg: b"1234".to_vec(),
+            chr: b"56789".to_vec(),
+            trainer: None,
+        };
+        let sums = checksums(&cart);
+        assert_eq!(sums.crc32, 0xCBF4_3926);
+        assert_eq!(sums.sha1, checksum::to_hex(&checksum::sha1(b"123456789")));
+
+        let games = [GameEntry {
+            crc32: 0xCBF4_3926,
+            sha1: "",
+            title: "Test",
+            region: Region::PAL,
+            mapper: 9,
+            mirroring: Some(Mirroring::Vertical),
+            has_battery: true,
+        }];
+        let game = find(&games, &sums).unwrap();
+        assert_eq!(game.title, "Test");
+
+        let mut header = Header::default();
+        let corrections = correct_header(game, &mut header);
+        assert_eq!(corrections.len(), 4);
+        assert_eq!(header.mapper, 9);
+        assert_eq!(header.mirroring(), Mirroring::Vertical);
+        assert!(header.has_battery);
+        assert_eq!(header.region, Region::PAL);
+
+        // already right, nothing to do
+        assert!(correct_header(game, &mut header).is_empty());
+
+        let other = RomChecksums {
+            crc32: 0,
+            sha1: String::new(),
+        };
+        assert!(find(&games, &other).is_none());
+    }
+}
@@ -0,0 +1,32 @@
+// save.rs - battery backed prg ram lives in <rom>.sav next to the rom between sessions
+use crate::memory::RAM;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn sav_path<P: AsRef<Path>>(rom_path: P) -> PathBuf {
+    rom_path.as_ref().with_extension("sav")
+}
+
+// a missing save file just means a fresh game
+pub fn load_battery_ram<P: AsRef<Path>>(path: P, ram: &mut RAM) -> io::Result<()> {
+    match fs::read(path) {
+        Ok(data) => {
+            ram.load_prg_ram(&data);
+            Ok(())
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn flush_battery_ram<P: AsRef<Path>>(path: P, ram: &mut RAM) -> io::Result<()> {
+    // write somewhere else first so a crash mid-write can't eat the old save
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("sav.tmp");
+    fs::write(&tmp_path, ram.get_prg_ram())?;
+    fs::rename(&tmp_path, path)?;
+    ram.clear_prg_ram_dirty();
+    Ok(())
+}
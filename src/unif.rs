@@ -0,0 +1,189 @@
+// unif.rs - UNIF roms, a chunked format that names the board instead of giving a mapper number
+// a 32 byte header then chunks of 4 byte id, 4 byte length and data
+use crate::cartridge::{Cartridge, Header, Region, CHR_BLOCK_SIZE, CHR_RAM_DEFAULT, PRG_BLOCK_SIZE, PRG_RAM_UNIT};
+
+const UNIF_HEADER_SIZE: usize = 32;
+
+pub fn is_unif(data: &[u8]) -> bool {
+    data.starts_with(b"UNIF")
+}
+
+// board names are the pcb names, sometimes with a NES-/HVC-/KONAMI- style prefix in front
+// only boards there's a mapper for are listed, anything else is better refused than run wrong
+pub fn board_to_mapper(board: &str) -> Option<u8> {
+    let name = ["NES-", "HVC-", "UNL-", "BTL-", "KONAMI-"]
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board);
+
+    let mapper = match name {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => 0,
+        "PNROM" | "PEEOROM" => 9,
+        "FJROM" | "FKROM" => 10,
+        // vrc6a (Akumajou Densetsu) and vrc6b with its address lines swapped (Madara, Esper Dream 2)
+        "351951" => 24,
+        "351949A" | "351948" => 26,
+        _ => return None,
+    };
+    Some(mapper)
+}
+
+fn chunk_index(id: &[u8]) -> usize {
+    match id[3] {
+        b'0'..=b'9' => (id[3] - b'0') as usize,
+        b'A'..=b'F' => (id[3] - b'A') as usize + 10,
+        _ => 0,
+    }
+}
+
+pub fn parse_unif(data: &[u8]) -> Result<Cartridge, String> {
+    if !is_unif(data) || data.len() < UNIF_HEADER_SIZE {
+        return Err("not a UNIF rom".to_string());
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = None;
+    let mut has_battery = false;
+    let mut tv_system = None;
+
+    let mut pos = UNIF_HEADER_SIZE;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let len = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        pos += 8;
+        if pos + len > data.len() {
+            return Err(format!("UNIF chunk {} runs off the end", String::from_utf8_lossy(id)));
+        }
+        let chunk = &data[pos..pos + len];
+        pos += len;
+
+        match &id[0..3] {
+            b"MAP" if id[3] == b'R' => {
+                let end = chunk.iter().position(|&b| b == 0).unwrap_or(chunk.len());
+                board = Some(String::from_utf8_lossy(&chunk[..end]).into_owned());
+            }
+            b"PRG" => prg_chunks[chunk_index(id)] = Some(chunk),
+            b"CHR" => chr_chunks[chunk_index(id)] = Some(chunk),
+            b"MIR" if id[3] == b'R' => mirroring = chunk.first().copied(),
+            b"BAT" if id[3] == b'R' => has_battery = true,
+            b"TVC" if id[3] == b'I' => tv_system = chunk.first().copied(),
+            _ => {}
+        }
+    }
+
+    let board = board.ok_or("UNIF rom has no MAPR chunk")?;
+    let mapper = board_to_mapper(&board).ok_or_else(|| format!("unsupported UNIF board {}", board))?;
+
+    let prg: Vec<u8> = prg_chunks.iter().flatten().flat_map(|c| c.iter().copied()).collect();
+    let chr: Vec<u8> = chr_chunks.iter().flatten().flat_map(|c| c.iter().copied()).collect();
+    if prg.is_empty() {
+        return Err("UNIF rom has no PRG chunks".to_string());
+    }
+
+    // 0 horizontal, 1 vertical, 2/3 single screen, 4 four screen, 5 is up to the mapper
+    let (mirror, four_screen) = match mirroring {
+        None | Some(0) | Some(5) => (0, false),
+        Some(mirror @ 1..=3) => (mirror, false),
+        Some(4) => (0, true),
+        Some(other) => return Err(format!("UNIF MIRR value {} isn't one we know", other)),
+    };
+
+    let header = Header {
+        num_prg_blocks: prg.len().div_ceil(PRG_BLOCK_SIZE),
+        num_chr_blocks: chr.len().div_ceil(CHR_BLOCK_SIZE),
+        has_trainer: false,
+        has_battery: has_battery,
+        mirror: mirror,
+        four_screen: four_screen,
+        mapper: mapper,
+        prg_ram_size: PRG_RAM_UNIT,
+        chr_ram_size: if chr.is_empty() { CHR_RAM_DEFAULT } else { 0 },
+        // 0 NTSC, 1 PAL, 2 works on either
+        region: if tv_system == Some(1) { Region::PAL } else { Region::NTSC },
+    };
+
+    Ok(Cartridge {
+        header: header,
+        prg: prg,
+        chr: chr,
+        trainer: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{load_cartridge, Mirroring};
+
+    fn chunk(data: &mut Vec<u8>, id: &[u8], body: &[u8]) {
+        data.extend_from_slice(id);
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(body);
+    }
+
+    #[test]
+    fn unif_tests() {
+        assert_eq!(board_to_mapper("NES-NROM-256"), Some(0));
+        assert_eq!(board_to_mapper("HVC-FKROM"), Some(10));
+        assert_eq!(board_to_mapper("PNROM"), Some(9));
+        assert_eq!(board_to_mapper("KONAMI-351951"), Some(24));
+        assert_eq!(board_to_mapper("351949A"), Some(26));
+        assert_eq!(board_to_mapper("NES-WHATROM"), None);
+        // there's no mmc1, so it isn't pretended there is
+        assert_eq!(board_to_mapper("NES-SNROM"), None);
+
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+        chunk(&mut data, b"MAPR", b"NES-PNROM\0");
+        // chunks can come in any order, PRG1 still goes after PRG0
+        chunk(&mut data, b"PRG1", &[0x22; PRG_BLOCK_SIZE]);
+        chunk(&mut data, b"PRG0", &[0x11; PRG_BLOCK_SIZE]);
+        chunk(&mut data, b"CHR0", &[0x33; CHR_BLOCK_SIZE]);
+        chunk(&mut data, b"MIRR", &[1]);
+        chunk(&mut data, b"BATR", &[0]);
+        chunk(&mut data, b"TVCI", &[1]);
+
+        let cart = load_cartridge(&data).unwrap();
+        assert_eq!(cart.header.mapper, 9);
+        assert_eq!(cart.header.num_prg_blocks, 2);
+        assert_eq!(cart.header.num_chr_blocks, 1);
+        assert_eq!(cart.header.mirroring(), Mirroring::Vertical);
+        assert!(cart.header.has_battery);
+        assert_eq!(cart.header.region, Region::PAL);
+        assert_eq!(cart.prg[0], 0x11);
+        assert_eq!(cart.prg[PRG_BLOCK_SIZE], 0x22);
+        assert_eq!(cart.chr.len(), CHR_BLOCK_SIZE);
+
+        // no chr chunks means chr ram, single screen mirroring comes through
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+        chunk(&mut data, b"MAPR", b"NES-NROM-256\0");
+        chunk(&mut data, b"PRG0", &[0; PRG_BLOCK_SIZE]);
+        chunk(&mut data, b"MIRR", &[3]);
+        let cart = load_cartridge(&data).unwrap();
+        assert_eq!(cart.header.chr_ram_size, CHR_RAM_DEFAULT);
+        assert_eq!(cart.header.mirroring(), Mirroring::SingleUpper);
+
+        // boards without a mapper here are refused
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+        chunk(&mut data, b"MAPR", b"UNROM\0");
+        chunk(&mut data, b"PRG0", &[0; PRG_BLOCK_SIZE]);
+        assert!(matches!(load_cartridge(&data), Err(e) if e.contains("unsupported")));
+
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+        chunk(&mut data, b"MAPR", b"NROM\0");
+        chunk(&mut data, b"PRG0", &[0; PRG_BLOCK_SIZE]);
+        chunk(&mut data, b"MIRR", &[9]);
+        assert!(load_cartridge(&data).is_err());
+
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+        chunk(&mut data, b"MAPR", b"MYSTERY\0");
+        chunk(&mut data, b"PRG0", &[0; PRG_BLOCK_SIZE]);
+        assert!(load_cartridge(&data).is_err());
+    }
+}
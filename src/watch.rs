@@ -0,0 +1,248 @@
+// watch.rs - read, write and execute watchpoints on the cpu and ppu buses
+// RAM checks these on every access, hitting one either pauses emulation or calls back into a script
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bus {
+    Cpu,
+    Ppu,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WatchHit {
+    pub id: usize,
+    pub bus: Bus,
+    pub access: Access,
+    pub address: u16,
+    pub value: u8,
+}
+
+pub enum WatchAction {
+    Pause,
+    Callback(Box<dyn FnMut(&WatchHit)>),
+}
+
+pub struct Watchpoint {
+    pub bus: Bus,
+    pub access: Access,
+    pub start: u16,
+    pub end: u16,
+    // only fire when this value is read, written or executed
+    pub value: Option<u8>,
+    pub action: WatchAction,
+}
+
+impl Watchpoint {
+    pub fn new(bus: Bus, access: Access, start: u16, end: u16) -> Self {
+        Watchpoint {
+            bus: bus,
+            access: access,
+            start: start,
+            end: end,
+            value: None,
+            action: WatchAction::Pause,
+        }
+    }
+
+    pub fn with_value(mut self, value: u8) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn with_callback<F: FnMut(&WatchHit) + 'static>(mut self, callback: F) -> Self {
+        self.action = WatchAction::Callback(Box::new(callback));
+        self
+    }
+
+    // command line form: r|w|x[:ppu]:ADDR[-END][=VALUE], hex addresses and values
+    // e.g. "w:6000-60FF", "x:C123", "w:ppu:3F00=0F"
+    pub fn parse(spec: &str) -> Result<Watchpoint, String> {
+        let mut parts: Vec<&str> = spec.split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(format!("bad watchpoint '{}'", spec));
+        }
+
+        let access = match parts[0] {
+            "r" => Access::Read,
+            "w" => Access::Write,
+            "x" => Access::Execute,
+            other => return Err(format!("unknown access '{}' in '{}'", other, spec)),
+        };
+
+        let bus = if parts.len() == 3 {
+            match parts[1] {
+                "cpu" => Bus::Cpu,
+                "ppu" => Bus::Ppu,
+                other => return Err(format!("unknown bus '{}' in '{}'", other, spec)),
+            }
+        } else {
+            Bus::Cpu
+        };
+
+        if bus == Bus::Ppu && access == Access::Execute {
+            return Err(format!("the ppu doesn't execute anything, '{}'", spec));
+        }
+
+        let range = parts.pop().unwrap();
+        let (range, value) = match range.find('=') {
+            Some(idx) => (&range[..idx], Some(parse_hex(&range[idx + 1..], spec)? as u8)),
+            None => (range, None),
+        };
+
+        let (start, end) = match range.find('-') {
+            Some(idx) => (parse_hex(&range[..idx], spec)?, parse_hex(&range[idx + 1..], spec)?),
+            None => {
+                let addr = parse_hex(range, spec)?;
+                (addr, addr)
+            }
+        };
+
+        if end < start {
+            return Err(format!("watchpoint range ends before it starts, '{}'", spec));
+        }
+
+        let mut point = Watchpoint::new(bus, access, start, end);
+        point.value = value;
+        Ok(point)
+    }
+
+    fn matches(&self, bus: Bus, access: Access, address: u16, value: u8) -> bool {
+        self.bus == bus
+            && self.access == access
+            && address >= self.start
+            && address <= self.end
+            && self.value.map_or(true, |v| v == value)
+    }
+}
+
+fn parse_hex(text: &str, spec: &str) -> Result<u16, String> {
+    let text = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(text, 16).map_err(|_| format!("bad hex '{}' in '{}'", text, spec))
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    points: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    paused: bool,
+    last_hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    // returns an id to remove it with later
+    pub fn add(&mut self, point: Watchpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.points.push((id, point));
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.points.len();
+        self.points.retain(|(point_id, _)| *point_id != id);
+        self.points.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // a pause lands once the current instruction has finished, we can't stop mid way through
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.last_hit.take()
+    }
+
+    #[inline]
+    pub fn check(&mut self, bus: Bus, access: Access, address: u16, value: u8) {
+        if self.points.is_empty() {
+            return;
+        }
+
+        for (id, point) in self.points.iter_mut() {
+            if !point.matches(bus, access, address, value) {
+                continue;
+            }
+
+            let hit = WatchHit {
+                id: *id,
+                bus: bus,
+                access: access,
+                address: address,
+                value: value,
+            };
+
+            match &mut point.action {
+                WatchAction::Pause => {
+                    self.paused = true;
+                    self.last_hit = Some(hit);
+                }
+                WatchAction::Callback(callback) => callback(&hit),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn watch_tests() {
+        let point = Watchpoint::parse("w:ppu:3F00-3F1F=0F").unwrap();
+        assert_eq!(point.bus, Bus::Ppu);
+        assert_eq!(point.access, Access::Write);
+        assert_eq!((point.start, point.end), (0x3F00, 0x3F1F));
+        assert_eq!(point.value, Some(0x0F));
+
+        assert!(Watchpoint::parse("x:ppu:2000").is_err());
+        assert!(Watchpoint::parse("q:2000").is_err());
+        assert!(Watchpoint::parse("r:2000-1000").is_err());
+
+        let mut points = Watchpoints::default();
+        let id = points.add(Watchpoint::parse("x:C000").unwrap());
+
+        points.check(Bus::Cpu, Access::Read, 0xC000, 0);
+        assert!(!points.is_paused());
+        points.check(Bus::Cpu, Access::Execute, 0xC000, 0xEA);
+        assert!(points.is_paused());
+        let hit = points.take_hit().unwrap();
+        assert_eq!((hit.id, hit.address, hit.value), (id, 0xC000, 0xEA));
+
+        points.resume();
+        assert!(points.remove(id));
+        assert!(!points.remove(id));
+
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        points.add(
+            Watchpoint::new(Bus::Cpu, Access::Write, 0x0000, 0x07FF)
+                .with_value(3)
+                .with_callback(move |_| counter.set(counter.get() + 1)),
+        );
+        points.check(Bus::Cpu, Access::Write, 0x10, 2);
+        points.check(Bus::Cpu, Access::Write, 0x10, 3);
+        points.check(Bus::Cpu, Access::Write, 0x800, 3);
+        assert_eq!(count.get(), 1);
+        assert!(!points.is_paused());
+    }
+}